@@ -1,5 +1,7 @@
 use clap::Command;
 
+mod pyth_feed_account;
+
 fn main() {
     let matches = Command::new("bonfida")
         .about("Development utils for Solana programs written in the Bonfida style")
@@ -7,6 +9,7 @@ fn main() {
         .subcommand(bonfida_autodoc::command().name("autodoc"))
         .subcommand(bonfida_autoproject::command().name("autoproject"))
         .subcommand(bonfida_benchviz::command().name("benchviz"))
+        .subcommand(pyth_feed_account::command())
         .subcommand_required(true)
         .arg_required_else_help(true)
         .get_matches();
@@ -15,6 +18,7 @@ fn main() {
         ("autodoc", m) => bonfida_autodoc::process(m),
         ("autoproject", m) => bonfida_autoproject::process(m),
         ("benchviz", m) => bonfida_benchviz::process(m),
+        ("pyth-feed-account", m) => pyth_feed_account::process(m),
         _ => unreachable!(),
     }
 }
@@ -0,0 +1,37 @@
+use bonfida_utils::pyth::{get_pyth_feed_account_key, utils::feed_id_from_hex};
+use clap::{Arg, ArgMatches, Command};
+
+pub fn command() -> Command<'static> {
+    Command::new("pyth-feed-account")
+        .about("Print the Pyth pull oracle price feed account of a feed id")
+        .arg(
+            Arg::with_name("feed-id")
+                .help("Hex encoded feed id, with or without a 0x prefix")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("shard")
+                .long("shard")
+                .takes_value(true)
+                .default_value("0"),
+        )
+}
+
+pub fn process(matches: &ArgMatches) {
+    let feed_id = match feed_id_from_hex(matches.value_of("feed-id").unwrap()) {
+        Ok(feed_id) => feed_id,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let shard = match matches.value_of("shard").unwrap().parse::<u16>() {
+        Ok(shard) => shard,
+        Err(e) => {
+            eprintln!("Invalid shard: {}", e);
+            return;
+        }
+    };
+    println!("{}", get_pyth_feed_account_key(shard, &feed_id));
+}
@@ -0,0 +1,30 @@
+use quote::quote;
+
+use proc_macro2::{Span, TokenStream};
+use syn::{LitByte, LitStr};
+
+pub fn process(item: TokenStream) -> TokenStream {
+    let str: LitStr = match syn::parse2(item) {
+        Ok(s) => s,
+        Err(e) => return e.to_compile_error(),
+    };
+    let value = str.value();
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(&value);
+    if hex.len() != 64 || !hex.bytes().all(|c| c.is_ascii_hexdigit()) {
+        return syn::Error::new(
+            str.span(),
+            "A feed id must be 64 hex characters, optionally prefixed with 0x",
+        )
+        .to_compile_error();
+    }
+    let bytes = (0..32).map(|i| {
+        let byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        LitByte::new(byte, Span::call_site())
+    });
+    quote!(
+        [#(#bytes),*]
+    )
+}
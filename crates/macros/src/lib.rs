@@ -4,6 +4,7 @@ mod borsh_size;
 mod compute_record_hash;
 mod compute_record_hash_v2;
 mod declare_id_with_central_state;
+mod feed_id;
 mod wrapped_pod;
 
 pub(crate) const SPL_NAME_SERVICE_HASH_PREFIX: &str = "SPL Name Service";
@@ -46,3 +47,9 @@ pub fn compute_hashv(item: TokenStream) -> TokenStream {
 pub fn compute_record_hash_v2(item: TokenStream) -> TokenStream {
     compute_record_hash_v2::process(item.into()).into()
 }
+
+/// Parses a hex Pyth feed id, with or without a `0x` prefix, into a `[u8; 32]` at compile time
+#[proc_macro]
+pub fn feed_id(item: TokenStream) -> TokenStream {
+    feed_id::process(item.into()).into()
+}
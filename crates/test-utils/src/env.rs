@@ -0,0 +1,365 @@
+//! Funded users holding token accounts across several mints, declared in one go and added to
+//! the genesis accounts of a [`ProgramTest`].
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey, rent::Rent};
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account, native_token::LAMPORTS_PER_SOL, signature::Keypair, signer::Signer,
+};
+use spl_associated_token_account_interface::address::get_associated_token_address_with_program_id;
+use spl_token::state::{AccountState, Mint};
+use std::collections::HashMap;
+
+use crate::{error::TestError, ProgramTestContextExt, ProgramTestExt};
+
+const DEFAULT_USER_LAMPORTS: u64 = 100 * LAMPORTS_PER_SOL;
+
+/// A mint of the environment, whose authority can mint more tokens
+#[derive(Debug)]
+pub struct TestMint {
+    pub key: Pubkey,
+    pub decimals: u8,
+    pub authority: Keypair,
+}
+
+impl TestMint {
+    /// Converts a whole number of tokens into base units
+    pub fn amount(&self, tokens: u64) -> u64 {
+        tokens * 10u64.pow(self.decimals as u32)
+    }
+}
+
+/// A user of the environment, with an associated token account for each mint
+#[derive(Debug)]
+pub struct TestUser {
+    pub keypair: Keypair,
+    /// Associated token account of each mint
+    pub token_accounts: HashMap<Pubkey, Pubkey>,
+}
+
+impl TestUser {
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    /// Returns the associated token account of the user for `mint`, which must be a mint of the
+    /// environment
+    pub fn ata(&self, mint: &Pubkey) -> Pubkey {
+        *self
+            .token_accounts
+            .get(mint)
+            .unwrap_or_else(|| panic!("{} has no token account for {}", self.pubkey(), mint))
+    }
+
+    /// Sends the instructions signed by the user, the context payer paying the fees
+    pub async fn sign_send_instructions(
+        &self,
+        context: &mut ProgramTestContext,
+        instructions: &[Instruction],
+    ) -> Result<(), TestError> {
+        context
+            .sign_send_instructions(instructions, &[&self.keypair])
+            .await
+    }
+
+    pub async fn lamports(&self, context: &mut ProgramTestContext) -> Result<u64, TestError> {
+        Ok(context.banks_client.get_balance(self.pubkey()).await?)
+    }
+
+    pub async fn token_balance(
+        &self,
+        context: &mut ProgramTestContext,
+        mint: &Pubkey,
+    ) -> Result<u64, TestError> {
+        Ok(context.get_token_account(self.ata(mint)).await?.amount)
+    }
+
+    /// Panics unless the user holds `expected` base units of `mint`
+    pub async fn assert_token_balance(
+        &self,
+        context: &mut ProgramTestContext,
+        mint: &Pubkey,
+        expected: u64,
+    ) {
+        let balance = self.token_balance(context, mint).await.unwrap();
+        assert_eq!(
+            balance,
+            expected,
+            "Unexpected balance of {} for user {}",
+            mint,
+            self.pubkey()
+        );
+    }
+}
+
+/// The mints and users created by a [`TestEnvBuilder`]
+#[derive(Debug)]
+pub struct TestEnv {
+    pub mints: Vec<TestMint>,
+    pub users: Vec<TestUser>,
+}
+
+impl TestEnv {
+    pub fn builder() -> TestEnvBuilder {
+        TestEnvBuilder::default()
+    }
+
+    pub fn mint(&self, index: usize) -> &TestMint {
+        &self.mints[index]
+    }
+
+    pub fn user(&self, index: usize) -> &TestUser {
+        &self.users[index]
+    }
+
+    /// Returns the balance of each user for the `mint`-th mint
+    pub async fn token_balances(
+        &self,
+        context: &mut ProgramTestContext,
+        mint: usize,
+    ) -> Result<Vec<u64>, TestError> {
+        let mint = self.mints[mint].key;
+        let mut balances = Vec::with_capacity(self.users.len());
+        for user in &self.users {
+            balances.push(user.token_balance(context, &mint).await?);
+        }
+        Ok(balances)
+    }
+
+    /// Panics unless the users hold the `expected` balances of the `mint`-th mint, in order
+    pub async fn assert_token_balances(
+        &self,
+        context: &mut ProgramTestContext,
+        mint: usize,
+        expected: &[u64],
+    ) {
+        let balances = self.token_balances(context, mint).await.unwrap();
+        assert_eq!(
+            balances, expected,
+            "Unexpected balances of {}",
+            self.mints[mint].key
+        );
+    }
+}
+
+struct MintSpec {
+    key: Option<Pubkey>,
+    decimals: u8,
+}
+
+/// Declares the mints, users and initial balances of a [`TestEnv`]
+pub struct TestEnvBuilder {
+    mints: Vec<MintSpec>,
+    users: usize,
+    lamports: u64,
+    /// Balance of each (user, mint), `None` standing for every user
+    balances: Vec<(Option<usize>, usize, u64)>,
+}
+
+impl Default for TestEnvBuilder {
+    fn default() -> Self {
+        Self {
+            mints: Vec::new(),
+            users: 0,
+            lamports: DEFAULT_USER_LAMPORTS,
+            balances: Vec::new(),
+        }
+    }
+}
+
+impl TestEnvBuilder {
+    /// Adds a mint at a new key
+    pub fn mint(mut self, decimals: u8) -> Self {
+        self.mints.push(MintSpec {
+            key: None,
+            decimals,
+        });
+        self
+    }
+
+    /// Adds a mint at `key`, e.g. to match a mint known by the program
+    pub fn mint_with_key(mut self, key: Pubkey, decimals: u8) -> Self {
+        self.mints.push(MintSpec {
+            key: Some(key),
+            decimals,
+        });
+        self
+    }
+
+    pub fn users(mut self, count: usize) -> Self {
+        self.users = count;
+        self
+    }
+
+    /// Lamports of each user, 100 SOL by default
+    pub fn lamports(mut self, lamports: u64) -> Self {
+        self.lamports = lamports;
+        self
+    }
+
+    /// Initial balance of every user for the `mint`-th mint, in base units
+    pub fn balance(mut self, mint: usize, amount: u64) -> Self {
+        self.balances.push((None, mint, amount));
+        self
+    }
+
+    /// Initial balance of the `user`-th user for the `mint`-th mint, in base units
+    pub fn user_balance(mut self, user: usize, mint: usize, amount: u64) -> Self {
+        self.balances.push((Some(user), mint, amount));
+        self
+    }
+
+    /// Adds the mints, users and their token accounts to the program test
+    pub fn build(self, program_test: &mut ProgramTest) -> TestEnv {
+        let mut balances = vec![vec![0; self.mints.len()]; self.users];
+        for (user, mint, amount) in self.balances {
+            assert!(mint < self.mints.len(), "Unknown mint {}", mint);
+            match user {
+                Some(user) => {
+                    assert!(user < self.users, "Unknown user {}", user);
+                    balances[user][mint] = amount;
+                }
+                None => balances.iter_mut().for_each(|b| b[mint] = amount),
+            }
+        }
+
+        let mints = self
+            .mints
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                let key = spec.key.unwrap_or_else(Pubkey::new_unique);
+                let authority = Keypair::new();
+                let mint = Mint {
+                    mint_authority: Some(authority.pubkey()).into(),
+                    supply: balances.iter().map(|b| b[i]).sum(),
+                    decimals: spec.decimals,
+                    is_initialized: true,
+                    freeze_authority: None.into(),
+                };
+                let mut data = vec![0; Mint::LEN];
+                mint.pack_into_slice(&mut data);
+                program_test.add_account(
+                    key,
+                    Account {
+                        lamports: Rent::default().minimum_balance(data.len()),
+                        data,
+                        owner: spl_token::ID,
+                        ..Account::default()
+                    },
+                );
+                TestMint {
+                    key,
+                    decimals: spec.decimals,
+                    authority,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let users = balances
+            .iter()
+            .map(|user_balances| {
+                let keypair = Keypair::new();
+                program_test.add_account_with_lamports(keypair.pubkey(), self.lamports);
+                let token_accounts = mints
+                    .iter()
+                    .zip(user_balances)
+                    .map(|(mint, amount)| {
+                        let ata = get_associated_token_address_with_program_id(
+                            &keypair.pubkey(),
+                            &mint.key,
+                            &spl_token::ID,
+                        );
+                        let account = spl_token::state::Account {
+                            mint: mint.key,
+                            owner: keypair.pubkey(),
+                            amount: *amount,
+                            state: AccountState::Initialized,
+                            ..spl_token::state::Account::default()
+                        };
+                        let mut data = vec![0; spl_token::state::Account::LEN];
+                        account.pack_into_slice(&mut data);
+                        program_test.add_account(
+                            ata,
+                            Account {
+                                lamports: Rent::default().minimum_balance(data.len()),
+                                data,
+                                owner: spl_token::ID,
+                                ..Account::default()
+                            },
+                        );
+                        (mint.key, ata)
+                    })
+                    .collect();
+                TestUser {
+                    keypair,
+                    token_accounts,
+                }
+            })
+            .collect();
+
+        TestEnv { mints, users }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_env() {
+        let mut program_test = ProgramTest::default();
+        let env = TestEnv::builder()
+            .mint(6)
+            .mint(9)
+            .users(3)
+            .balance(0, 1_000)
+            .user_balance(2, 1, 5)
+            .build(&mut program_test);
+        let mut context = program_test.start_with_context().await;
+
+        env.assert_token_balances(&mut context, 0, &[1_000, 1_000, 1_000])
+            .await;
+        env.assert_token_balances(&mut context, 1, &[0, 0, 5]).await;
+        let mint = context
+            .get_token_account(env.user(0).ata(&env.mint(1).key))
+            .await;
+        assert!(mint.is_ok());
+        assert_eq!(
+            env.user(1).lamports(&mut context).await.unwrap(),
+            DEFAULT_USER_LAMPORTS
+        );
+        assert_eq!(env.mint(1).amount(2), 2_000_000_000);
+
+        let (sender, receiver, usdc) = (env.user(0), env.user(1), env.mint(0));
+        let ix = spl_token::instruction::transfer(
+            &spl_token::ID,
+            &sender.ata(&usdc.key),
+            &receiver.ata(&usdc.key),
+            &sender.pubkey(),
+            &[],
+            400,
+        )
+        .unwrap();
+        sender
+            .sign_send_instructions(&mut context, &[ix])
+            .await
+            .unwrap();
+        env.assert_token_balances(&mut context, 0, &[600, 1_400, 1_000])
+            .await;
+
+        context
+            .mint_tokens(&usdc.authority, &usdc.key, &env.user(2).ata(&usdc.key), 1)
+            .await
+            .unwrap();
+        env.user(2)
+            .assert_token_balance(&mut context, &usdc.key, 1_001)
+            .await;
+        let supply = context
+            .banks_client
+            .get_packed_account_data::<Mint>(usdc.key)
+            .await
+            .unwrap()
+            .supply;
+        assert_eq!(supply, 3_001);
+    }
+}
@@ -1,5 +1,13 @@
-use solana_program::program_error::ProgramError;
+use num_traits::FromPrimitive;
+use solana_program::{instruction::InstructionError, program_error::ProgramError};
 use solana_program_test::{BanksClientError, ProgramTestError};
+use solana_sdk::transaction::TransactionError;
+use std::fmt::Debug;
+
+use crate::execution::ExecutionResult;
+
+/// Number of log lines printed by failed program error assertions
+const ASSERTION_LOG_LINES: usize = 10;
 
 #[derive(Debug)]
 pub enum TestError {
@@ -9,6 +17,10 @@ pub enum TestError {
     AccountDoesNotExist,
     InvalidTokenAccount,
     InvalidTimestampForWarp,
+    /// The simulation did not report the result of the transaction
+    MissingSimulationResult,
+    /// The tag at the start of the account data is not the expected one
+    InvalidAccountTag,
 }
 
 impl From<BanksClientError> for TestError {
@@ -28,3 +40,287 @@ impl From<ProgramTestError> for TestError {
         Self::ProgramTestError(e)
     }
 }
+
+impl TestError {
+    /// Returns the error of the failed transaction, if any
+    pub fn transaction_error(&self) -> Option<&TransactionError> {
+        match self {
+            Self::BanksClientError(BanksClientError::TransactionError(err))
+            | Self::BanksClientError(BanksClientError::SimulationError { err, .. }) => Some(err),
+            _ => None,
+        }
+    }
+
+    /// Returns the index of the failed instruction along with its error
+    pub fn instruction_error(&self) -> Option<(u8, &InstructionError)> {
+        self.transaction_error().and_then(instruction_error)
+    }
+
+    /// Returns the index of the failed instruction along with its custom error code
+    pub fn custom_error_code(&self) -> Option<(u8, u32)> {
+        self.transaction_error().and_then(custom_error_code)
+    }
+
+    /// Returns the index of the failed instruction along with its decoded custom error
+    pub fn custom_error<E: FromPrimitive>(&self) -> Option<(u8, E)> {
+        let (index, code) = self.custom_error_code()?;
+        Some((index, E::from_u32(code)?))
+    }
+
+    /// Returns the logs of the failed transaction, only available when it went through a
+    /// preflight simulation
+    pub fn logs(&self) -> &[String] {
+        match self {
+            Self::BanksClientError(BanksClientError::SimulationError { logs, .. }) => logs,
+            _ => &[],
+        }
+    }
+}
+
+fn instruction_error(err: &TransactionError) -> Option<(u8, &InstructionError)> {
+    match err {
+        TransactionError::InstructionError(index, err) => Some((*index, err)),
+        _ => None,
+    }
+}
+
+fn custom_error_code(err: &TransactionError) -> Option<(u8, u32)> {
+    match instruction_error(err)? {
+        (index, InstructionError::Custom(code)) => Some((index, *code)),
+        _ => None,
+    }
+}
+
+/// The outcome of a transaction, as checked by [`assert_program_error!`](crate::assert_program_error)
+pub trait TransactionOutcome {
+    /// Returns the error of the transaction if it failed
+    fn transaction_error(&self) -> Option<&TransactionError>;
+
+    /// Returns the logs of the transaction, empty when they are not available
+    fn logs(&self) -> &[String];
+}
+
+impl TransactionOutcome for TestError {
+    fn transaction_error(&self) -> Option<&TransactionError> {
+        TestError::transaction_error(self)
+    }
+
+    fn logs(&self) -> &[String] {
+        TestError::logs(self)
+    }
+}
+
+impl TransactionOutcome for ExecutionResult {
+    fn transaction_error(&self) -> Option<&TransactionError> {
+        self.result.as_ref().err()
+    }
+
+    fn logs(&self) -> &[String] {
+        &self.logs
+    }
+}
+
+impl TransactionOutcome for Result<(), TestError> {
+    fn transaction_error(&self) -> Option<&TransactionError> {
+        self.as_ref().err().and_then(TestError::transaction_error)
+    }
+
+    fn logs(&self) -> &[String] {
+        self.as_ref().err().map_or(&[], TestError::logs)
+    }
+}
+
+impl TransactionOutcome for Result<ExecutionResult, TestError> {
+    fn transaction_error(&self) -> Option<&TransactionError> {
+        match self {
+            Ok(result) => result.transaction_error(),
+            Err(err) => err.transaction_error(),
+        }
+    }
+
+    fn logs(&self) -> &[String] {
+        match self {
+            Ok(result) => &result.logs,
+            Err(err) => err.logs(),
+        }
+    }
+}
+
+/// Returns a function decoding the custom error codes of `E`, used by
+/// [`assert_program_error!`](crate::assert_program_error)
+#[doc(hidden)]
+pub fn decoder_of<E: FromPrimitive + Debug>(_: &E) -> fn(u32) -> Option<String> {
+    |code| E::from_u32(code).map(|e| format!("{:?}", e))
+}
+
+/// Panics unless `outcome` failed with the custom error `expected_code`, see
+/// [`assert_program_error!`](crate::assert_program_error)
+#[doc(hidden)]
+#[track_caller]
+pub fn assert_custom_error(
+    outcome: &impl TransactionOutcome,
+    expected_code: u32,
+    expected_name: &str,
+    decode: fn(u32) -> Option<String>,
+) {
+    let failure = match outcome.transaction_error() {
+        None => "the transaction did not fail with an instruction error".to_owned(),
+        Some(err) => match instruction_error(err) {
+            Some((_, InstructionError::Custom(code))) if *code == expected_code => return,
+            Some((index, InstructionError::Custom(code))) => {
+                let name = decode(*code).unwrap_or_else(|| "an unknown error".to_owned());
+                format!("instruction {} failed with {} ({})", index, name, code)
+            }
+            Some((index, err)) => format!("instruction {} failed with {:?}", index, err),
+            None => format!("the transaction failed with {:?}", err),
+        },
+    };
+    let logs = outcome.logs();
+    let last_logs = &logs[logs.len().saturating_sub(ASSERTION_LOG_LINES)..];
+    let mut message = format!(
+        "Expected the program error {} ({}) but {}",
+        expected_name, expected_code, failure
+    );
+    if !last_logs.is_empty() {
+        message.push_str("\nLast logs:");
+        for line in last_logs {
+            message.push_str("\n    ");
+            message.push_str(line);
+        }
+    }
+    panic!("{}", message);
+}
+
+/// Asserts that a transaction failed with a custom program error.
+///
+/// The outcome can be the result of
+/// [`sign_send_instructions`](crate::ProgramTestContextExt::sign_send_instructions), an
+/// [`ExecutionResult`] or a [`TestError`], and the error any fieldless enum implementing
+/// `FromPrimitive` and `Debug`, so that unexpected errors are printed by name. The last logs of
+/// the transaction are printed along, only available for an [`ExecutionResult`], e.g. from
+/// [`sign_send_instructions_with_metadata`](crate::ProgramTestContextExt::sign_send_instructions_with_metadata).
+///
+/// ```ignore
+/// let result = prg_test_ctx
+///     .sign_send_instructions_with_metadata(&[ix], &[&owner])
+///     .await;
+/// assert_program_error!(result, MyError::WrongOwner);
+/// ```
+#[macro_export]
+macro_rules! assert_program_error {
+    ($outcome:expr, $expected:expr $(,)?) => {{
+        let expected = $expected;
+        let decode = $crate::error::decoder_of(&expected);
+        let expected_name = format!("{:?}", expected);
+        $crate::error::assert_custom_error(&$outcome, expected as u32, &expected_name, decode)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProgramTestContextExt;
+    use num_derive::FromPrimitive;
+    use solana_program::{
+        account_info::AccountInfo, entrypoint::ProgramResult, instruction::Instruction,
+        pubkey::Pubkey,
+    };
+    use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+
+    const PROGRAM: Pubkey = Pubkey::new_from_array([5; 32]);
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+    enum ExampleError {
+        WrongOwner = 1,
+        InsufficientFunds = 2,
+    }
+
+    /// Fails with the custom error given as instruction data, if any
+    fn process(_: &Pubkey, _: &[AccountInfo], data: &[u8]) -> ProgramResult {
+        match data[0] {
+            0 => Ok(()),
+            code => Err(ProgramError::Custom(code as u32)),
+        }
+    }
+
+    async fn start() -> ProgramTestContext {
+        ProgramTest::new("errors", PROGRAM, processor!(process))
+            .start_with_context()
+            .await
+    }
+
+    /// A successful instruction followed by one failing with `code`
+    fn instructions(code: u8) -> [Instruction; 2] {
+        [
+            Instruction::new_with_bytes(PROGRAM, &[0], vec![]),
+            Instruction::new_with_bytes(PROGRAM, &[code], vec![]),
+        ]
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_assert_program_error() {
+        let mut context = start().await;
+        let result = context.sign_send_instructions(&instructions(2), &[]).await;
+        assert_program_error!(result, ExampleError::InsufficientFunds);
+
+        let err = result.unwrap_err();
+        assert_eq!(err.custom_error_code(), Some((1, 2)));
+        assert_eq!(
+            err.custom_error::<ExampleError>(),
+            Some((1, ExampleError::InsufficientFunds))
+        );
+        assert_eq!(
+            err.instruction_error(),
+            Some((1, &InstructionError::Custom(2)))
+        );
+        assert!(matches!(
+            err,
+            TestError::BanksClientError(BanksClientError::TransactionError(_))
+        ));
+        assert!(err.logs().is_empty());
+        assert_program_error!(err, ExampleError::InsufficientFunds);
+
+        // Unknown codes are not decoded
+        let result = context.sign_send_instructions(&instructions(3), &[]).await;
+        assert_eq!(
+            result.as_ref().unwrap_err().custom_error::<ExampleError>(),
+            None
+        );
+
+        let result = context
+            .sign_send_instructions_with_metadata(&instructions(1), &[])
+            .await;
+        assert_program_error!(result, ExampleError::WrongOwner);
+        let result = result.unwrap();
+        assert!(result
+            .logs
+            .last()
+            .unwrap()
+            .ends_with("failed: custom program error: 0x1"));
+        assert_program_error!(result, ExampleError::WrongOwner);
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    #[should_panic(
+        expected = "Expected the program error WrongOwner (1) but instruction 1 failed \
+        with InsufficientFunds (2)\nLast logs:\n    \
+        Program LbUiWL3xVV8hTFYBVdbTNrpDo41NKS6o3LHHuDzjfcY invoke [1]"
+    )]
+    async fn test_assert_program_error_message() {
+        let mut context = start().await;
+        let result = context
+            .sign_send_instructions_with_metadata(&instructions(2), &[])
+            .await;
+        assert_program_error!(result, ExampleError::WrongOwner);
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    #[should_panic(
+        expected = "Expected the program error WrongOwner (1) but the transaction did not fail"
+    )]
+    async fn test_assert_program_error_success() {
+        let mut context = start().await;
+        let result = context.sign_send_instructions(&instructions(0), &[]).await;
+        assert_program_error!(result, ExampleError::WrongOwner);
+    }
+}
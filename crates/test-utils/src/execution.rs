@@ -0,0 +1,381 @@
+//! Results of executed or simulated transactions, with their logs, compute units, return data
+//! and CPI tree.
+use solana_program::pubkey::Pubkey;
+use solana_sdk::transaction::TransactionError;
+use std::str::FromStr;
+
+/// Data set with `set_return_data` by the last program which did so
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReturnData {
+    pub program_id: Pubkey,
+    pub data: Vec<u8>,
+}
+
+/// An instruction invoked through a CPI, with its account indexes resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InnerInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+    /// 1 for transaction instructions, 2 for the instructions they invoke and so on
+    pub stack_height: u8,
+}
+
+/// A program invocation parsed from the logs, with the invocations it made
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Invocation {
+    pub program_id: Pubkey,
+    /// 1 for transaction instructions
+    pub depth: usize,
+    /// Compute units consumed by the invocation, including the invocations it made
+    pub compute_units: Option<u64>,
+    /// Messages logged with `msg!`
+    pub logs: Vec<String>,
+    /// The error message if the invocation failed
+    pub error: Option<String>,
+    pub children: Vec<Invocation>,
+}
+
+impl Invocation {
+    /// Iterates over this invocation and all the invocations it made, depth first
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Invocation> + '_> {
+        Box::new(std::iter::once(self).chain(self.children.iter().flat_map(|c| c.iter())))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionResult {
+    pub result: Result<(), TransactionError>,
+    pub logs: Vec<String>,
+    pub compute_units_consumed: u64,
+    pub return_data: Option<ReturnData>,
+    /// Inner instructions of each transaction instruction, only reported by simulations: always
+    /// `None` for sent transactions since `TransactionMetadata` carries no inner instructions
+    pub inner_instructions: Option<Vec<Vec<InnerInstruction>>>,
+    /// The CPI tree, one root per transaction instruction
+    pub invocations: Vec<Invocation>,
+}
+
+impl ExecutionResult {
+    /// Iterates over every invocation of the CPI tree, depth first
+    pub fn all_invocations(&self) -> impl Iterator<Item = &Invocation> {
+        self.invocations.iter().flat_map(|i| i.iter())
+    }
+
+    /// Returns the compute units consumed by each invocation of `program_id`
+    pub fn compute_units_of(&self, program_id: &Pubkey) -> Vec<u64> {
+        self.all_invocations()
+            .filter(|i| &i.program_id == program_id)
+            .filter_map(|i| i.compute_units)
+            .collect()
+    }
+
+    /// Returns `true` if a program logged a message containing `needle`
+    pub fn has_log(&self, needle: &str) -> bool {
+        self.all_invocations()
+            .any(|i| i.logs.iter().any(|l| l.contains(needle)))
+    }
+}
+
+/// Parses the CPI tree out of transaction logs
+pub fn parse_invocations(logs: &[String]) -> Vec<Invocation> {
+    let mut roots = Vec::new();
+    let mut stack: Vec<Invocation> = Vec::new();
+    for line in logs {
+        let rest = match line.strip_prefix("Program ") {
+            Some(rest) => rest,
+            None => continue,
+        };
+        if let Some(message) = rest.strip_prefix("log: ") {
+            if let Some(top) = stack.last_mut() {
+                top.logs.push(message.to_owned());
+            }
+            continue;
+        }
+        let (program, event) = match rest.split_once(' ') {
+            Some(x) => x,
+            None => continue,
+        };
+        let program_id = match Pubkey::from_str(program) {
+            Ok(key) => key,
+            Err(_) => continue,
+        };
+        if let Some(depth) = event
+            .strip_prefix("invoke [")
+            .and_then(|d| d.strip_suffix(']'))
+            .and_then(|d| d.parse().ok())
+        {
+            stack.push(Invocation {
+                program_id,
+                depth,
+                ..Invocation::default()
+            });
+        } else if let Some(consumed) = event.strip_prefix("consumed ") {
+            if let Some(top) = stack.last_mut() {
+                top.compute_units = consumed.split(' ').next().and_then(|c| c.parse().ok());
+            }
+        } else if event == "success" || event.starts_with("failed: ") {
+            let mut invocation = match stack.pop() {
+                Some(i) => i,
+                None => continue,
+            };
+            invocation.error = event.strip_prefix("failed: ").map(str::to_owned);
+            match stack.last_mut() {
+                Some(parent) => parent.children.push(invocation),
+                None => roots.push(invocation),
+            }
+        }
+    }
+    // Invocations interrupted without a result, e.g. when running out of compute units
+    while let Some(invocation) = stack.pop() {
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(invocation),
+            None => roots.push(invocation),
+        }
+    }
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProgramTestContextExt;
+    use solana_program::program_pack::Pack;
+    use solana_program_test::ProgramTest;
+    use solana_sdk::signer::Signer;
+
+    /// The system program id, all zeros
+    const SYSTEM_PROGRAM: Pubkey = Pubkey::new_from_array([0; 32]);
+
+    fn logs(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_nested_invocations() {
+        let (outer, inner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let logs = logs(&[
+            &format!("Program {} invoke [1]", outer),
+            "Program log: Instruction: Deposit",
+            &format!("Program {} invoke [2]", inner),
+            "Program log: Instruction: Transfer",
+            &format!("Program {} consumed 4645 of 194322 compute units", inner),
+            &format!("Program {} success", inner),
+            &format!("Program {} invoke [2]", SYSTEM_PROGRAM),
+            &format!("Program {} success", SYSTEM_PROGRAM),
+            &format!("Program return: {} AQID", outer),
+            &format!("Program {} consumed 12000 of 200000 compute units", outer),
+            &format!("Program {} success", outer),
+            &format!("Program {} invoke [1]", inner),
+            &format!("Program {} consumed 3000 of 188000 compute units", inner),
+            &format!("Program {} success", inner),
+        ]);
+        let invocations = parse_invocations(&logs);
+        assert_eq!(
+            invocations,
+            vec![
+                Invocation {
+                    program_id: outer,
+                    depth: 1,
+                    compute_units: Some(12000),
+                    logs: vec!["Instruction: Deposit".to_owned()],
+                    error: None,
+                    children: vec![
+                        Invocation {
+                            program_id: inner,
+                            depth: 2,
+                            compute_units: Some(4645),
+                            logs: vec!["Instruction: Transfer".to_owned()],
+                            error: None,
+                            children: vec![],
+                        },
+                        // Builtin programs do not log their compute units
+                        Invocation {
+                            program_id: SYSTEM_PROGRAM,
+                            depth: 2,
+                            ..Invocation::default()
+                        },
+                    ],
+                },
+                Invocation {
+                    program_id: inner,
+                    depth: 1,
+                    compute_units: Some(3000),
+                    ..Invocation::default()
+                },
+            ]
+        );
+
+        let result = ExecutionResult {
+            result: Ok(()),
+            logs,
+            compute_units_consumed: 15000,
+            return_data: None,
+            inner_instructions: None,
+            invocations,
+        };
+        assert_eq!(result.compute_units_of(&outer), vec![12000]);
+        assert_eq!(result.compute_units_of(&inner), vec![4645, 3000]);
+        assert!(result.compute_units_of(&SYSTEM_PROGRAM).is_empty());
+        assert_eq!(
+            result
+                .all_invocations()
+                .map(|i| (i.program_id, i.depth))
+                .collect::<Vec<_>>(),
+            vec![(outer, 1), (inner, 2), (SYSTEM_PROGRAM, 2), (inner, 1)]
+        );
+        assert!(result.has_log("Transfer"));
+        assert!(!result.has_log("Withdraw"));
+    }
+
+    #[test]
+    fn test_parse_failed_inner_invocation() {
+        let (outer, inner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let invocations = parse_invocations(&logs(&[
+            &format!("Program {} invoke [1]", outer),
+            &format!("Program {} invoke [2]", inner),
+            "Program log: Error: insufficient funds",
+            &format!("Program {} consumed 2000 of 190000 compute units", inner),
+            &format!("Program {} failed: custom program error: 0x1", inner),
+            &format!("Program {} consumed 10000 of 200000 compute units", outer),
+            &format!("Program {} failed: custom program error: 0x1", outer),
+        ]));
+        assert_eq!(invocations.len(), 1);
+        let root = &invocations[0];
+        assert_eq!(root.error.as_deref(), Some("custom program error: 0x1"));
+        assert_eq!(root.compute_units, Some(10000));
+        assert_eq!(
+            root.children,
+            vec![Invocation {
+                program_id: inner,
+                depth: 2,
+                compute_units: Some(2000),
+                logs: vec!["Error: insufficient funds".to_owned()],
+                error: Some("custom program error: 0x1".to_owned()),
+                children: vec![],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_compute_budget_exhaustion() {
+        let (outer, inner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let invocations = parse_invocations(&logs(&[
+            &format!("Program {} invoke [1]", outer),
+            &format!("Program {} invoke [2]", inner),
+            &format!("Program {} consumed 1400 of 1400 compute units", inner),
+            &format!(
+                "Program {} failed: exceeded CUs meter at BPF instruction #712",
+                inner
+            ),
+            &format!("Program {} consumed 1400 of 1400 compute units", outer),
+            &format!("Program {} failed: Computational budget exceeded", outer),
+        ]));
+        assert_eq!(invocations.len(), 1);
+        assert_eq!(
+            invocations[0].error.as_deref(),
+            Some("Computational budget exceeded")
+        );
+        assert_eq!(invocations[0].compute_units, Some(1400));
+        assert_eq!(
+            invocations[0].children[0].error.as_deref(),
+            Some("exceeded CUs meter at BPF instruction #712")
+        );
+
+        // Truncated logs leave invocations without a result, kept in the tree
+        let invocations = parse_invocations(&logs(&[
+            &format!("Program {} invoke [1]", outer),
+            &format!("Program {} invoke [2]", inner),
+            "Log truncated",
+        ]));
+        assert_eq!(
+            invocations,
+            vec![Invocation {
+                program_id: outer,
+                depth: 1,
+                children: vec![Invocation {
+                    program_id: inner,
+                    depth: 2,
+                    ..Invocation::default()
+                }],
+                ..Invocation::default()
+            }]
+        );
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_execution_result() {
+        let mut context = ProgramTest::default().start_with_context().await;
+        let authority = context.payer.pubkey();
+        let mint = context.create_mint(&authority, None, 6).await.unwrap();
+
+        // The token program returns the size of the token accounts of the mint
+        let ix = spl_token::instruction::get_account_data_size(&spl_token::ID, &mint).unwrap();
+        // Simulated first, the same transaction being rejected once processed
+        let simulated = context
+            .simulate_instructions(std::slice::from_ref(&ix), &[])
+            .await
+            .unwrap();
+        let result = context
+            .sign_send_instructions_with_metadata(std::slice::from_ref(&ix), &[])
+            .await
+            .unwrap();
+        result.result.clone().unwrap();
+        assert_eq!(
+            result.return_data,
+            Some(ReturnData {
+                program_id: spl_token::ID,
+                data: (spl_token::state::Account::LEN as u64)
+                    .to_le_bytes()
+                    .to_vec(),
+            })
+        );
+        assert_eq!(
+            result.compute_units_of(&spl_token::ID),
+            vec![result.compute_units_consumed]
+        );
+        assert!(result.compute_units_consumed > 0);
+        assert_eq!(result.inner_instructions, None);
+
+        assert_eq!(simulated.result, Ok(()));
+        assert_eq!(simulated.return_data, result.return_data);
+        assert_eq!(simulated.inner_instructions, Some(vec![vec![]]));
+
+        // The associated token account program invokes the token and system programs
+        let owner = Pubkey::new_unique();
+        let ix = spl_associated_token_account::instruction::create_associated_token_account(
+            &authority,
+            &owner,
+            &mint,
+            &spl_token::ID,
+        );
+        let simulated = context
+            .simulate_instructions(std::slice::from_ref(&ix), &[])
+            .await
+            .unwrap();
+        let result = context
+            .sign_send_instructions_with_metadata(&[ix], &[])
+            .await
+            .unwrap();
+        result.result.clone().unwrap();
+        assert_eq!(result.invocations.len(), 1);
+        let root = &result.invocations[0];
+        assert_eq!(root.program_id, spl_associated_token_account::ID);
+        assert_eq!(root.compute_units, Some(result.compute_units_consumed));
+        assert!(root.children.iter().all(|c| c.depth == 2));
+        assert!(root.children.iter().any(|c| c.program_id == SYSTEM_PROGRAM));
+        let token_units = result.compute_units_of(&spl_token::ID);
+        assert!(!token_units.is_empty());
+        assert!(token_units.iter().sum::<u64>() < result.compute_units_consumed);
+
+        let inner = &simulated.inner_instructions.unwrap()[0];
+        assert_eq!(
+            inner.iter().map(|i| i.program_id).collect::<Vec<_>>(),
+            root.children
+                .iter()
+                .map(|c| c.program_id)
+                .collect::<Vec<_>>()
+        );
+        assert!(inner.iter().all(|i| i.stack_height == 2));
+    }
+}
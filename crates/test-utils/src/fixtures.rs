@@ -0,0 +1,275 @@
+//! Account and program fixtures dumped from a cluster, to replay its state offline.
+//!
+//! Accounts are read from the JSON files written by `solana account <KEY> --output json`, and
+//! programs from `.so` files named either after their program id or after a
+//! `<name>-keypair.json` file of the same directory, as written by `cargo build-sbf`.
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use solana_program::{bpf_loader, pubkey::Pubkey, rent::Rent};
+use solana_sdk::{account::Account, signature::Keypair, signer::Signer};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Key and owner substitutions applied to the loaded fixtures, e.g. to move the accounts of a
+/// mainnet program under the id of a locally built one
+#[derive(Debug, Clone, Default)]
+pub struct FixtureRewrites {
+    keys: HashMap<Pubkey, Pubkey>,
+    owners: HashMap<Pubkey, Pubkey>,
+}
+
+impl FixtureRewrites {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the account or program `from` at `to`
+    pub fn key(mut self, from: Pubkey, to: Pubkey) -> Self {
+        self.keys.insert(from, to);
+        self
+    }
+
+    /// Gives the accounts owned by `from` to `to`
+    pub fn owner(mut self, from: Pubkey, to: Pubkey) -> Self {
+        self.owners.insert(from, to);
+        self
+    }
+
+    fn apply(&self, key: Pubkey, mut account: Account) -> (Pubkey, Account) {
+        if let Some(owner) = self.owners.get(&account.owner) {
+            account.owner = *owner;
+        }
+        (*self.keys.get(&key).unwrap_or(&key), account)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UiAccount {
+    lamports: u64,
+    /// The data and its encoding
+    data: (String, String),
+    owner: String,
+    executable: bool,
+    rent_epoch: u64,
+}
+
+#[derive(Deserialize)]
+struct KeyedUiAccount {
+    pubkey: String,
+    account: UiAccount,
+}
+
+fn parse_pubkey(path: &Path, value: &str) -> Pubkey {
+    Pubkey::from_str(value)
+        .unwrap_or_else(|e| panic!("Invalid pubkey {} in {}: {}", value, path.display(), e))
+}
+
+/// Reads an account dumped with `solana account <KEY> --output json`
+pub fn read_account_fixture(path: impl AsRef<Path>) -> (Pubkey, Account) {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    let keyed: KeyedUiAccount = serde_json::from_str(&source)
+        .unwrap_or_else(|e| panic!("Invalid account fixture {}: {}", path.display(), e));
+    let (data, encoding) = &keyed.account.data;
+    if encoding != "base64" {
+        panic!(
+            "Unsupported {} encoding in {}, dump the account with the base64 encoding",
+            encoding,
+            path.display()
+        );
+    }
+    let data = STANDARD
+        .decode(data)
+        .unwrap_or_else(|e| panic!("Invalid base64 data in {}: {}", path.display(), e));
+    let account = Account {
+        lamports: keyed.account.lamports,
+        data,
+        owner: parse_pubkey(path, &keyed.account.owner),
+        executable: keyed.account.executable,
+        rent_epoch: keyed.account.rent_epoch,
+    };
+    (parse_pubkey(path, &keyed.pubkey), account)
+}
+
+fn read_keypair(path: &Path) -> Keypair {
+    let source = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    let bytes: Vec<u8> = serde_json::from_str(&source)
+        .unwrap_or_else(|e| panic!("Invalid keypair {}: {}", path.display(), e));
+    Keypair::try_from(bytes.as_slice())
+        .unwrap_or_else(|e| panic!("Invalid keypair {}: {}", path.display(), e))
+}
+
+/// Reads a program, returning its id and an executable account of the BPF loader
+pub fn read_program_fixture(path: impl AsRef<Path>) -> (Pubkey, Account) {
+    let path = path.as_ref();
+    let name = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_else(|| panic!("Invalid program file name {}", path.display()));
+    let program_id = Pubkey::from_str(name).unwrap_or_else(|_| {
+        let keypair_path = path.with_file_name(format!("{}-keypair.json", name));
+        if !keypair_path.exists() {
+            panic!(
+                "{} must be named after its program id or have a {}-keypair.json file",
+                path.display(),
+                name
+            );
+        }
+        read_keypair(&keypair_path).pubkey()
+    });
+    let data =
+        fs::read(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    let account = Account {
+        lamports: Rent::default().minimum_balance(data.len()).max(1),
+        data,
+        owner: bpf_loader::id(),
+        executable: true,
+        rent_epoch: 0,
+    };
+    (program_id, account)
+}
+
+/// Reads the accounts and programs of a directory, in the order of their file names
+pub fn read_fixtures_dir(
+    path: impl AsRef<Path>,
+    rewrites: &FixtureRewrites,
+) -> Vec<(Pubkey, Account)> {
+    let path = path.as_ref();
+    let mut files = fs::read_dir(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e))
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    files.sort();
+    files
+        .into_iter()
+        .filter(|file| file.is_file())
+        .filter_map(|file| {
+            let file_name = file.file_name()?.to_str()?;
+            if file_name.ends_with("-keypair.json") {
+                None
+            } else if file_name.ends_with(".json") {
+                Some(read_account_fixture(&file))
+            } else if file_name.ends_with(".so") {
+                Some(read_program_fixture(&file))
+            } else {
+                None
+            }
+        })
+        .map(|(key, account)| rewrites.apply(key, account))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ProgramTestContextExt, ProgramTestExt};
+    use solana_program::{
+        instruction::Instruction, program_option::COption, program_pack::Pack, pubkey,
+    };
+    use solana_program_test::{ProgramTest, ProgramTestContext};
+
+    const MINT: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    /// The public key of `noop-keypair.json`
+    const NOOP_PROGRAM: Pubkey = pubkey!("AeY8jBNjCqDH9L37TZWFe2TvxtBJ8t86Ce4NzTVoViUq");
+
+    fn fixtures_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    async fn invoke(context: &mut ProgramTestContext, program_id: Pubkey) -> Result<(), String> {
+        let ix = Instruction::new_with_bytes(program_id, &[], vec![]);
+        context
+            .sign_send_instructions(&[ix], &[])
+            .await
+            .map_err(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn test_read_account_fixture() {
+        let (key, account) = read_account_fixture(fixtures_dir().join(format!("{}.json", MINT)));
+        assert_eq!(key, MINT);
+        assert_eq!(account.lamports, 1_461_600);
+        assert_eq!(account.owner, spl_token::ID);
+        assert!(!account.executable);
+        assert_eq!(account.rent_epoch, u64::MAX);
+        assert_eq!(account.data.len(), 82);
+        let mint = spl_token::state::Mint::unpack(&account.data).unwrap();
+        assert_eq!(mint.supply, 5_000_000_000_000);
+        assert_eq!(mint.decimals, 6);
+        assert!(matches!(mint.freeze_authority, COption::Some(_)));
+    }
+
+    #[test]
+    fn test_read_program_fixture() {
+        let (program_id, account) = read_program_fixture(fixtures_dir().join("noop.so"));
+        assert_eq!(program_id, NOOP_PROGRAM);
+        assert_eq!(account.owner, bpf_loader::id());
+        assert!(account.executable);
+        assert_eq!(
+            account.data,
+            fs::read(fixtures_dir().join("noop.so")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_fixtures_dir() {
+        // Sorted by file name, the README and the keypair being skipped
+        let fixtures = read_fixtures_dir(fixtures_dir(), &FixtureRewrites::new());
+        assert_eq!(
+            fixtures.iter().map(|(key, _)| *key).collect::<Vec<_>>(),
+            vec![MINT, NOOP_PROGRAM]
+        );
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_add_accounts_from_dir() {
+        let mut program_test = ProgramTest::default();
+        let keys = program_test.add_accounts_from_dir(fixtures_dir());
+        assert_eq!(keys, vec![MINT, NOOP_PROGRAM]);
+        let mut context = program_test.start_with_context().await;
+
+        let mint = context.get_mint(MINT).await.unwrap();
+        assert_eq!(mint.supply, 5_000_000_000_000);
+        invoke(&mut context, NOOP_PROGRAM).await.unwrap();
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_add_accounts_from_dir_with_rewrites() {
+        let (mint_key, program_id) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let rewrites = FixtureRewrites::new()
+            .key(MINT, mint_key)
+            .key(NOOP_PROGRAM, program_id)
+            .owner(spl_token::ID, spl_token_2022_interface::ID);
+        let mut program_test = ProgramTest::default();
+        let keys = program_test.add_accounts_from_dir_with(fixtures_dir(), &rewrites);
+        assert_eq!(keys, vec![mint_key, program_id]);
+        let mut context = program_test.start_with_context().await;
+
+        assert!(context
+            .banks_client
+            .get_account(MINT)
+            .await
+            .unwrap()
+            .is_none());
+        let account = context
+            .banks_client
+            .get_account(mint_key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.owner, spl_token_2022_interface::ID);
+        let mint = context.get_mint_2022(mint_key).await.unwrap();
+        assert_eq!(mint.decimals, 6);
+
+        invoke(&mut context, program_id).await.unwrap();
+        assert!(invoke(&mut context, NOOP_PROGRAM).await.is_err());
+    }
+}
@@ -1,6 +1,11 @@
+pub mod env;
 pub mod error;
+pub mod execution;
+pub mod fixtures;
 pub mod program_test_context_ext;
 pub mod program_test_ext;
+pub mod pyth;
+pub mod snapshot;
 
 pub use program_test_context_ext::ProgramTestContextExt;
 pub use program_test_ext::ProgramTestExt;
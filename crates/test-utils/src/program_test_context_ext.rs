@@ -1,15 +1,296 @@
 use async_trait::async_trait;
+use bonfida_utils::{
+    oracle::{MockOracle, MOCK_ORACLE_OWNER},
+    pyth::PYTH_RECEIVER,
+};
+use borsh::BorshDeserialize;
+use bytemuck::Pod;
 use solana_program::{
     clock::Clock, example_mocks::solana_sdk::system_instruction, instruction::Instruction,
-    program_pack::Pack, pubkey::Pubkey, rent::Rent,
+    program_error::ProgramError, program_pack::Pack, pubkey::Pubkey, rent::Rent,
+};
+use solana_program_test::{ProgramTestContext, ProgramTestError};
+use solana_sdk::{account::Account, signature::Keypair, signer::Signer, transaction::Transaction};
+use spl_token_2022_interface::{
+    extension::{BaseState, StateWithExtensions},
+    instruction as token_instruction,
 };
-use solana_program_test::ProgramTestContext;
-use solana_sdk::{signature::Keypair, signer::Signer, transaction::Transaction};
+use std::ops::{Deref, DerefMut};
 
-use crate::error::TestError;
+use crate::{
+    error::TestError,
+    execution::{parse_invocations, ExecutionResult, InnerInstruction, ReturnData},
+    pyth::{legacy_price_account_data, price_update_v2_data, PythFeed, PYTH_ORACLE_PROGRAM},
+    snapshot::{AccountDecoder, AccountsSnapshot, SnapshotDiff},
+};
 
 const NANOSECONDS_IN_SECOND: u128 = 1_000_000_000;
 
+fn seconds_to_slots(context: &ProgramTestContext, seconds: u64) -> u64 {
+    (seconds as u128 * NANOSECONDS_IN_SECOND / context.genesis_config().ns_per_slot())
+        .try_into()
+        .unwrap()
+}
+
+fn slots_to_seconds(context: &ProgramTestContext, slots: u64) -> i64 {
+    (slots as u128 * context.genesis_config().ns_per_slot() / NANOSECONDS_IN_SECOND)
+        .try_into()
+        .unwrap()
+}
+
+/// Warps `slots` forward and then moves the timestamp by `seconds`, the timestamp of the warped
+/// bank being overwritten. The epoch start timestamp follows the slots unless the clock is
+/// `frozen`.
+async fn move_clock(
+    context: &mut ProgramTestContext,
+    slots: u64,
+    seconds: i64,
+    frozen: bool,
+) -> Result<Clock, TestError> {
+    let previous = context.banks_client.get_sysvar::<Clock>().await?;
+    if slots > 0 {
+        context.warp_to_slot(previous.slot + slots)?;
+        context.last_blockhash = context.banks_client.get_latest_blockhash().await?;
+    }
+    let mut clock = context.banks_client.get_sysvar::<Clock>().await?;
+    clock.unix_timestamp = previous.unix_timestamp + seconds;
+    if clock.epoch != previous.epoch {
+        let epoch_schedule = &context.genesis_config().epoch_schedule;
+        let elapsed_slots = clock.slot - epoch_schedule.get_first_slot_in_epoch(clock.epoch);
+        let elapsed = if frozen {
+            0
+        } else {
+            slots_to_seconds(context, elapsed_slots)
+        };
+        clock.epoch_start_timestamp = (clock.unix_timestamp - elapsed).max(previous.unix_timestamp);
+    }
+    context.set_sysvar(&clock);
+    Ok(clock)
+}
+
+/// Number of slots until the first slot of `epoch`
+async fn slots_to_epoch(context: &mut ProgramTestContext, epoch: u64) -> Result<u64, TestError> {
+    let clock = context.banks_client.get_sysvar::<Clock>().await?;
+    let first_slot = context
+        .genesis_config()
+        .epoch_schedule
+        .get_first_slot_in_epoch(epoch);
+    if first_slot <= clock.slot {
+        return Err(ProgramTestError::InvalidWarpSlot.into());
+    }
+    Ok(first_slot - clock.slot)
+}
+
+/// A context whose clock is frozen, see [`ProgramTestContextExt::freeze_clock`]. It derefs to the
+/// context, and the clock is unfrozen once it is dropped.
+pub struct FrozenClock<'a> {
+    context: &'a mut ProgramTestContext,
+}
+
+impl FrozenClock<'_> {
+    /// Moves the timestamp forward by `seconds`, keeping the slot
+    pub async fn advance_seconds(&mut self, seconds: u64) -> Result<Clock, TestError> {
+        move_clock(self.context, 0, seconds.try_into().unwrap(), true).await
+    }
+
+    /// Moves the clock forward by `slots`, keeping the timestamp
+    pub async fn advance_slots(&mut self, slots: u64) -> Result<Clock, TestError> {
+        move_clock(self.context, slots, 0, true).await
+    }
+
+    /// Moves the clock forward to the first slot of `epoch`, keeping the timestamp
+    pub async fn advance_to_epoch(&mut self, epoch: u64) -> Result<Clock, TestError> {
+        let slots = slots_to_epoch(self.context, epoch).await?;
+        self.advance_slots(slots).await
+    }
+}
+
+impl Deref for FrozenClock<'_> {
+    type Target = ProgramTestContext;
+
+    fn deref(&self) -> &Self::Target {
+        self.context
+    }
+}
+
+impl DerefMut for FrozenClock<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.context
+    }
+}
+
+fn sign_transaction(
+    context: &ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Transaction {
+    let mut transaction = Transaction::new_with_payer(instructions, Some(&context.payer.pubkey()));
+    let mut payer_signers = Vec::with_capacity(1 + signers.len());
+    payer_signers.push(&context.payer);
+    for s in signers {
+        payer_signers.push(s);
+    }
+    transaction.partial_sign(&payer_signers, context.last_blockhash);
+    transaction
+}
+
+/// Size of the tag at the start of the accounts of the program template
+const ACCOUNT_TAG_LEN: usize = 8;
+
+/// Reads the base state of a token account or mint, returning the token program owning it
+async fn get_token_state<S: BaseState + Pack>(
+    context: &mut ProgramTestContext,
+    key: Pubkey,
+) -> Result<(Pubkey, S), TestError> {
+    let account = context
+        .banks_client
+        .get_account(key)
+        .await?
+        .ok_or(TestError::AccountDoesNotExist)?;
+    if account.owner != spl_token::ID && account.owner != spl_token_2022_interface::ID {
+        return Err(TestError::InvalidTokenAccount);
+    }
+    let state = StateWithExtensions::<S>::unpack(&account.data)?.base;
+    Ok((account.owner, state))
+}
+
+/// Returns the token program, mint and decimals of a token account
+async fn get_token_account_mint(
+    context: &mut ProgramTestContext,
+    key: Pubkey,
+) -> Result<(Pubkey, Pubkey, u8), TestError> {
+    let (token_program, account) =
+        get_token_state::<spl_token_2022_interface::state::Account>(context, key).await?;
+    let (_, mint) =
+        get_token_state::<spl_token_2022_interface::state::Mint>(context, account.mint).await?;
+    Ok((token_program, account.mint, mint.decimals))
+}
+
+/// Returns the data following the tag of an account, which must be `tag`
+async fn get_tagged_account_data(
+    context: &mut ProgramTestContext,
+    key: Pubkey,
+    tag: u64,
+) -> Result<Vec<u8>, TestError> {
+    let mut data = context
+        .banks_client
+        .get_account(key)
+        .await?
+        .ok_or(TestError::AccountDoesNotExist)?
+        .data;
+    if data.len() < ACCOUNT_TAG_LEN {
+        return Err(TestError::InvalidAccountTag);
+    }
+    let found = u64::from_le_bytes(data[..ACCOUNT_TAG_LEN].try_into().unwrap());
+    if found != tag {
+        return Err(TestError::InvalidAccountTag);
+    }
+    data.drain(..ACCOUNT_TAG_LEN);
+    Ok(data)
+}
+
+async fn create_mint_with_program(
+    context: &mut ProgramTestContext,
+    token_program: &Pubkey,
+    mint_authority: &Pubkey,
+    freeze_authority: Option<&Pubkey>,
+    decimals: u8,
+) -> Result<Pubkey, TestError> {
+    let mint = Keypair::new();
+    let space = spl_token_2022_interface::state::Mint::LEN;
+    let rent = context.banks_client.get_sysvar::<Rent>().await?;
+    let instructions = [
+        system_instruction::create_account(
+            &context.payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(space),
+            space as u64,
+            token_program,
+        ),
+        token_instruction::initialize_mint2(
+            token_program,
+            &mint.pubkey(),
+            mint_authority,
+            freeze_authority,
+            decimals,
+        )?,
+    ];
+    context
+        .sign_send_instructions(&instructions, &[&mint])
+        .await?;
+    Ok(mint.pubkey())
+}
+
+async fn create_associated_token_accounts(
+    context: &mut ProgramTestContext,
+    token_program: &Pubkey,
+    mint: Pubkey,
+    owners: &[Pubkey],
+) -> Result<Vec<Pubkey>, TestError> {
+    let mut instructions = Vec::with_capacity(owners.len());
+    let mut account_keys = Vec::with_capacity(owners.len());
+    for o in owners {
+        let i = spl_associated_token_account::instruction::create_associated_token_account(
+            &context.payer.pubkey(),
+            o,
+            &mint,
+            token_program,
+        );
+        account_keys.push(i.accounts[1].pubkey);
+        instructions.push(i);
+    }
+    for c in instructions.chunks(10) {
+        context.sign_send_instructions(c, &[]).await?;
+    }
+    Ok(account_keys)
+}
+
+async fn wrap_sol_with_program(
+    context: &mut ProgramTestContext,
+    token_program: &Pubkey,
+    owner: &Keypair,
+    lamports: u64,
+) -> Result<Pubkey, TestError> {
+    let mut instructions = Vec::with_capacity(4);
+    let native_mint = if *token_program == spl_token_2022_interface::ID {
+        // Unlike the SPL Token one, the Token-2022 native mint is not part of the genesis
+        let native_mint = spl_token_2022_interface::native_mint::ID;
+        if context
+            .banks_client
+            .get_account(native_mint)
+            .await?
+            .is_none()
+        {
+            instructions.push(token_instruction::create_native_mint(
+                token_program,
+                &context.payer.pubkey(),
+            )?);
+        }
+        native_mint
+    } else {
+        spl_token::native_mint::ID
+    };
+    let create =
+        spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+            &context.payer.pubkey(),
+            &owner.pubkey(),
+            &native_mint,
+            token_program,
+        );
+    let account = create.accounts[1].pubkey;
+    instructions.push(create);
+    instructions.push(system_instruction::transfer(
+        &owner.pubkey(),
+        &account,
+        lamports,
+    ));
+    instructions.push(token_instruction::sync_native(token_program, &account)?);
+    context
+        .sign_send_instructions(&instructions, &[owner])
+        .await?;
+    Ok(account)
+}
+
 #[async_trait]
 pub trait ProgramTestContextExt {
     async fn mint_tokens(
@@ -31,13 +312,165 @@ pub trait ProgramTestContextExt {
         signers: &[&Keypair],
     ) -> Result<(), TestError>;
 
+    /// Same as [`ProgramTestContextExt::sign_send_instructions`], returning the result of the
+    /// transaction along with its logs, compute units and return data instead of failing
+    async fn sign_send_instructions_with_metadata(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<ExecutionResult, TestError>;
+
+    /// Simulates the instructions without committing them, also reporting inner instructions
+    async fn simulate_instructions(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<ExecutionResult, TestError>;
+
+    /// Fetches the accounts, including the ones which do not exist
+    async fn snapshot_accounts(&mut self, keys: &[Pubkey]) -> Result<AccountsSnapshot, TestError>;
+
+    /// Same as [`ProgramTestContextExt::sign_send_instructions`], returning the changes made to
+    /// the `watched` accounts. Their state is decoded with the first of `decoders` handling them,
+    /// SPL token and Token-2022 accounts and mints being always decoded.
+    async fn sign_send_instructions_with_diff(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        watched: &[Pubkey],
+        decoders: &[&dyn AccountDecoder],
+    ) -> Result<SnapshotDiff, TestError>;
+
+    /// Moves the clock forward to `timestamp`, see [`ProgramTestContextExt::advance_seconds`]
     async fn warp_to_timestamp(&mut self, timestamp: i64) -> Result<(), TestError>;
+
+    /// Moves the timestamp forward by `seconds`, along with the matching number of slots
+    async fn advance_seconds(&mut self, seconds: u64) -> Result<Clock, TestError>;
+
+    /// Moves the clock forward by `slots`, along with the matching number of seconds
+    async fn advance_slots(&mut self, slots: u64) -> Result<Clock, TestError>;
+
+    /// Moves the clock forward to the first slot of `epoch`, see
+    /// [`ProgramTestContextExt::advance_slots`]. Unlike `ProgramTestContext::warp_to_epoch`, the
+    /// timestamp follows the slots.
+    async fn advance_to_epoch(&mut self, epoch: u64) -> Result<Clock, TestError>;
+
+    /// Decouples the slots from the timestamp until the returned [`FrozenClock`] is dropped:
+    /// advancing the slots through it keeps the timestamp and advancing the seconds keeps the slot
+    fn freeze_clock(&mut self) -> FrozenClock<'_>;
+
     async fn initialize_token_accounts(
         &mut self,
         mint: Pubkey,
         owners: &[Pubkey],
     ) -> Result<Vec<Pubkey>, TestError>;
 
+    /// Same as [`ProgramTestContextExt::initialize_token_accounts`] for a Token-2022 mint
+    async fn initialize_token_accounts_2022(
+        &mut self,
+        mint: Pubkey,
+        owners: &[Pubkey],
+    ) -> Result<Vec<Pubkey>, TestError>;
+
+    /// Creates a mint and returns its key
+    async fn create_mint(
+        &mut self,
+        mint_authority: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+        decimals: u8,
+    ) -> Result<Pubkey, TestError>;
+
+    /// Creates a Token-2022 mint without extensions and returns its key
+    async fn create_mint_2022(
+        &mut self,
+        mint_authority: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+        decimals: u8,
+    ) -> Result<Pubkey, TestError>;
+
+    async fn get_mint(&mut self, key: Pubkey) -> Result<spl_token::state::Mint, TestError>;
+
+    /// Reads the base state of a mint of either token program, ignoring its extensions
+    async fn get_mint_2022(
+        &mut self,
+        key: Pubkey,
+    ) -> Result<spl_token_2022_interface::state::Mint, TestError>;
+
+    /// Reads the base state of a token account of either token program, ignoring its extensions
+    async fn get_token_account_2022(
+        &mut self,
+        key: Pubkey,
+    ) -> Result<spl_token_2022_interface::state::Account, TestError>;
+
+    /// Transfers tokens between two accounts of either token program, the program and the
+    /// decimals being read from the source account and its mint
+    async fn transfer_tokens(
+        &mut self,
+        source: &Pubkey,
+        destination: &Pubkey,
+        authority: &Keypair,
+        amount: u64,
+    ) -> Result<(), TestError>;
+
+    /// Approves `delegate` to transfer or burn `amount` tokens of `source`, see
+    /// [`ProgramTestContextExt::transfer_tokens`]
+    async fn approve_tokens(
+        &mut self,
+        source: &Pubkey,
+        delegate: &Pubkey,
+        owner: &Keypair,
+        amount: u64,
+    ) -> Result<(), TestError>;
+
+    /// Burns tokens of an account, see [`ProgramTestContextExt::transfer_tokens`]
+    async fn burn_tokens(
+        &mut self,
+        account: &Pubkey,
+        authority: &Keypair,
+        amount: u64,
+    ) -> Result<(), TestError>;
+
+    /// Freezes a token account of either token program
+    async fn freeze_token_account(
+        &mut self,
+        account: &Pubkey,
+        freeze_authority: &Keypair,
+    ) -> Result<(), TestError>;
+
+    /// Thaws a token account of either token program
+    async fn thaw_token_account(
+        &mut self,
+        account: &Pubkey,
+        freeze_authority: &Keypair,
+    ) -> Result<(), TestError>;
+
+    /// Closes an empty or native token account of either token program, sending its lamports to
+    /// `destination`
+    async fn close_token_account(
+        &mut self,
+        account: &Pubkey,
+        destination: &Pubkey,
+        authority: &Keypair,
+    ) -> Result<(), TestError>;
+
+    /// Wraps `lamports` of `owner` into its associated account of the native mint, created if
+    /// needed, and returns that account
+    async fn wrap_sol(&mut self, owner: &Keypair, lamports: u64) -> Result<Pubkey, TestError>;
+
+    /// Same as [`ProgramTestContextExt::wrap_sol`] with the Token-2022 native mint
+    async fn wrap_sol_2022(&mut self, owner: &Keypair, lamports: u64) -> Result<Pubkey, TestError>;
+
+    /// Deserializes the Borsh state following the 8 byte tag of an account, which must be `tag`
+    async fn get_account_data_borsh<T: BorshDeserialize>(
+        &mut self,
+        key: Pubkey,
+        tag: u64,
+    ) -> Result<T, TestError>;
+
+    /// Reads the `Pod` state following the 8 byte tag of an account, which must be `tag`
+    async fn get_account_data_pod<T: Pod>(&mut self, key: Pubkey, tag: u64)
+        -> Result<T, TestError>;
+
     async fn get_current_timestamp(&mut self) -> Result<i64, TestError>;
 
     async fn initialize_new_account(
@@ -45,6 +478,39 @@ pub trait ProgramTestContextExt {
         space: usize,
         program_id: Pubkey,
     ) -> Result<Pubkey, TestError>;
+
+    /// Creates or overwrites the mock oracle account `key`
+    async fn set_mock_oracle(&mut self, key: &Pubkey, oracle: &MockOracle)
+        -> Result<(), TestError>;
+
+    /// Sets the price of the mock oracle account `key`, published at the current clock time
+    async fn set_mock_oracle_price(
+        &mut self,
+        key: &Pubkey,
+        price: i64,
+        exponent: i32,
+    ) -> Result<(), TestError>;
+
+    /// Creates or overwrites the Pyth pull oracle price update of `feed`, posted at the current
+    /// slot, and returns its key
+    async fn set_pyth_price_v2(
+        &mut self,
+        feed: impl Into<PythFeed> + Send,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Result<Pubkey, TestError>;
+
+    /// Creates or overwrites the legacy Pyth price account `key`, published at the current slot
+    async fn set_pyth_price_legacy(
+        &mut self,
+        key: &Pubkey,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Result<(), TestError>;
 }
 
 #[async_trait]
@@ -56,8 +522,10 @@ impl ProgramTestContextExt for ProgramTestContext {
         token_account: &Pubkey,
         amount: u64,
     ) -> Result<(), TestError> {
-        let mint_instruction = spl_token::instruction::mint_to(
-            &spl_token::ID,
+        let (token_program, _) =
+            get_token_state::<spl_token_2022_interface::state::Mint>(self, *mint_pubkey).await?;
+        let mint_instruction = token_instruction::mint_to(
+            &token_program,
             mint_pubkey,
             token_account,
             &mint_authority.pubkey(),
@@ -87,54 +555,349 @@ impl ProgramTestContextExt for ProgramTestContext {
         instructions: &[Instruction],
         signers: &[&Keypair],
     ) -> Result<(), TestError> {
-        let mut transaction = Transaction::new_with_payer(instructions, Some(&self.payer.pubkey()));
-        let mut payer_signers = Vec::with_capacity(1 + signers.len());
-        payer_signers.push(&self.payer);
-        for s in signers {
-            payer_signers.push(s);
-        }
-        transaction.partial_sign(&payer_signers, self.last_blockhash);
+        let transaction = sign_transaction(self, instructions, signers);
         self.banks_client.process_transaction(transaction).await?;
         Ok(())
     }
 
+    async fn sign_send_instructions_with_metadata(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<ExecutionResult, TestError> {
+        let transaction = sign_transaction(self, instructions, signers);
+        let outcome = self
+            .banks_client
+            .process_transaction_with_metadata(transaction)
+            .await?;
+        let (logs, compute_units_consumed, return_data) = match outcome.metadata {
+            Some(m) => (m.log_messages, m.compute_units_consumed, m.return_data),
+            None => (Vec::new(), 0, None),
+        };
+        Ok(ExecutionResult {
+            result: outcome.result,
+            invocations: parse_invocations(&logs),
+            logs,
+            compute_units_consumed,
+            return_data: return_data.map(|r| ReturnData {
+                program_id: r.program_id,
+                data: r.data,
+            }),
+            inner_instructions: None,
+        })
+    }
+
+    async fn simulate_instructions(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<ExecutionResult, TestError> {
+        let transaction = sign_transaction(self, instructions, signers);
+        let account_keys = transaction.message.account_keys.clone();
+        let outcome = self.banks_client.simulate_transaction(transaction).await?;
+        let result = outcome.result.ok_or(TestError::MissingSimulationResult)?;
+        let details = outcome.simulation_details;
+        let inner_instructions = details
+            .as_ref()
+            .and_then(|d| d.inner_instructions.as_ref())
+            .map(|list| {
+                list.iter()
+                    .map(|inner| {
+                        inner
+                            .iter()
+                            .map(|i| InnerInstruction {
+                                program_id: account_keys[i.instruction.program_id_index as usize],
+                                accounts: i
+                                    .instruction
+                                    .accounts
+                                    .iter()
+                                    .map(|a| account_keys[*a as usize])
+                                    .collect(),
+                                data: i.instruction.data.clone(),
+                                stack_height: i.stack_height,
+                            })
+                            .collect()
+                    })
+                    .collect()
+            });
+        let logs = details.as_ref().map(|d| d.logs.clone()).unwrap_or_default();
+        Ok(ExecutionResult {
+            result,
+            invocations: parse_invocations(&logs),
+            logs,
+            compute_units_consumed: details.as_ref().map_or(0, |d| d.units_consumed),
+            return_data: details.and_then(|d| d.return_data).map(|r| ReturnData {
+                program_id: r.program_id,
+                data: r.data,
+            }),
+            inner_instructions,
+        })
+    }
+
+    async fn snapshot_accounts(&mut self, keys: &[Pubkey]) -> Result<AccountsSnapshot, TestError> {
+        let mut accounts = Vec::with_capacity(keys.len());
+        for key in keys {
+            accounts.push((*key, self.banks_client.get_account(*key).await?));
+        }
+        Ok(AccountsSnapshot { accounts })
+    }
+
+    async fn sign_send_instructions_with_diff(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+        watched: &[Pubkey],
+        decoders: &[&dyn AccountDecoder],
+    ) -> Result<SnapshotDiff, TestError> {
+        let before = self.snapshot_accounts(watched).await?;
+        self.sign_send_instructions(instructions, signers).await?;
+        let after = self.snapshot_accounts(watched).await?;
+        Ok(before.diff(&after, decoders))
+    }
+
     async fn warp_to_timestamp(&mut self, timestamp: i64) -> Result<(), TestError> {
-        let mut clock = self.banks_client.get_sysvar::<Clock>().await?;
+        let clock = self.banks_client.get_sysvar::<Clock>().await?;
         if clock.unix_timestamp > timestamp {
             return Err(TestError::InvalidTimestampForWarp);
         }
-        let ns_per_slot = self.genesis_config().ns_per_slot();
-        let time_delta_ns =
-            (timestamp - clock.unix_timestamp).unsigned_abs() as u128 * NANOSECONDS_IN_SECOND;
-        let number_of_slots_to_warp: u64 = (time_delta_ns / ns_per_slot).try_into().unwrap();
-
-        clock.unix_timestamp = timestamp;
-        self.set_sysvar(&clock);
-        self.warp_to_slot(clock.slot + number_of_slots_to_warp)?;
+        self.advance_seconds((timestamp - clock.unix_timestamp).unsigned_abs())
+            .await?;
         Ok(())
     }
 
+    async fn advance_seconds(&mut self, seconds: u64) -> Result<Clock, TestError> {
+        let slots = seconds_to_slots(self, seconds);
+        move_clock(self, slots, seconds.try_into().unwrap(), false).await
+    }
+
+    async fn advance_slots(&mut self, slots: u64) -> Result<Clock, TestError> {
+        let seconds = slots_to_seconds(self, slots);
+        move_clock(self, slots, seconds, false).await
+    }
+
+    async fn advance_to_epoch(&mut self, epoch: u64) -> Result<Clock, TestError> {
+        let slots = slots_to_epoch(self, epoch).await?;
+        self.advance_slots(slots).await
+    }
+
+    fn freeze_clock(&mut self) -> FrozenClock<'_> {
+        FrozenClock { context: self }
+    }
+
     async fn initialize_token_accounts(
         &mut self,
         mint: Pubkey,
         owners: &[Pubkey],
     ) -> Result<Vec<Pubkey>, TestError> {
-        let mut instructions = Vec::with_capacity(owners.len());
-        let mut account_keys = Vec::with_capacity(owners.len());
-        for o in owners {
-            let i = spl_associated_token_account::instruction::create_associated_token_account(
-                &self.payer.pubkey(),
-                o,
-                &mint,
-                &spl_token::ID,
-            );
-            account_keys.push(i.accounts[1].pubkey);
-            instructions.push(i);
-        }
-        for c in instructions.chunks(10) {
-            self.sign_send_instructions(c, &[]).await?;
+        create_associated_token_accounts(self, &spl_token::ID, mint, owners).await
+    }
+
+    async fn initialize_token_accounts_2022(
+        &mut self,
+        mint: Pubkey,
+        owners: &[Pubkey],
+    ) -> Result<Vec<Pubkey>, TestError> {
+        create_associated_token_accounts(self, &spl_token_2022_interface::ID, mint, owners).await
+    }
+
+    async fn create_mint(
+        &mut self,
+        mint_authority: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+        decimals: u8,
+    ) -> Result<Pubkey, TestError> {
+        create_mint_with_program(
+            self,
+            &spl_token::ID,
+            mint_authority,
+            freeze_authority,
+            decimals,
+        )
+        .await
+    }
+
+    async fn create_mint_2022(
+        &mut self,
+        mint_authority: &Pubkey,
+        freeze_authority: Option<&Pubkey>,
+        decimals: u8,
+    ) -> Result<Pubkey, TestError> {
+        create_mint_with_program(
+            self,
+            &spl_token_2022_interface::ID,
+            mint_authority,
+            freeze_authority,
+            decimals,
+        )
+        .await
+    }
+
+    async fn get_mint(&mut self, key: Pubkey) -> Result<spl_token::state::Mint, TestError> {
+        let raw_account = self
+            .banks_client
+            .get_account(key)
+            .await?
+            .ok_or(TestError::AccountDoesNotExist)?;
+        if raw_account.owner != spl_token::ID {
+            return Err(TestError::InvalidTokenAccount);
         }
-        Ok(account_keys)
+        Ok(spl_token::state::Mint::unpack(&raw_account.data)?)
+    }
+
+    async fn get_mint_2022(
+        &mut self,
+        key: Pubkey,
+    ) -> Result<spl_token_2022_interface::state::Mint, TestError> {
+        Ok(get_token_state(self, key).await?.1)
+    }
+
+    async fn get_token_account_2022(
+        &mut self,
+        key: Pubkey,
+    ) -> Result<spl_token_2022_interface::state::Account, TestError> {
+        Ok(get_token_state(self, key).await?.1)
+    }
+
+    async fn transfer_tokens(
+        &mut self,
+        source: &Pubkey,
+        destination: &Pubkey,
+        authority: &Keypair,
+        amount: u64,
+    ) -> Result<(), TestError> {
+        let (token_program, mint, decimals) = get_token_account_mint(self, *source).await?;
+        let ix = token_instruction::transfer_checked(
+            &token_program,
+            source,
+            &mint,
+            destination,
+            &authority.pubkey(),
+            &[],
+            amount,
+            decimals,
+        )?;
+        self.sign_send_instructions(&[ix], &[authority]).await
+    }
+
+    async fn approve_tokens(
+        &mut self,
+        source: &Pubkey,
+        delegate: &Pubkey,
+        owner: &Keypair,
+        amount: u64,
+    ) -> Result<(), TestError> {
+        let (token_program, mint, decimals) = get_token_account_mint(self, *source).await?;
+        let ix = token_instruction::approve_checked(
+            &token_program,
+            source,
+            &mint,
+            delegate,
+            &owner.pubkey(),
+            &[],
+            amount,
+            decimals,
+        )?;
+        self.sign_send_instructions(&[ix], &[owner]).await
+    }
+
+    async fn burn_tokens(
+        &mut self,
+        account: &Pubkey,
+        authority: &Keypair,
+        amount: u64,
+    ) -> Result<(), TestError> {
+        let (token_program, mint, decimals) = get_token_account_mint(self, *account).await?;
+        let ix = token_instruction::burn_checked(
+            &token_program,
+            account,
+            &mint,
+            &authority.pubkey(),
+            &[],
+            amount,
+            decimals,
+        )?;
+        self.sign_send_instructions(&[ix], &[authority]).await
+    }
+
+    async fn freeze_token_account(
+        &mut self,
+        account: &Pubkey,
+        freeze_authority: &Keypair,
+    ) -> Result<(), TestError> {
+        let (token_program, mint, _) = get_token_account_mint(self, *account).await?;
+        let ix = token_instruction::freeze_account(
+            &token_program,
+            account,
+            &mint,
+            &freeze_authority.pubkey(),
+            &[],
+        )?;
+        self.sign_send_instructions(&[ix], &[freeze_authority])
+            .await
+    }
+
+    async fn thaw_token_account(
+        &mut self,
+        account: &Pubkey,
+        freeze_authority: &Keypair,
+    ) -> Result<(), TestError> {
+        let (token_program, mint, _) = get_token_account_mint(self, *account).await?;
+        let ix = token_instruction::thaw_account(
+            &token_program,
+            account,
+            &mint,
+            &freeze_authority.pubkey(),
+            &[],
+        )?;
+        self.sign_send_instructions(&[ix], &[freeze_authority])
+            .await
+    }
+
+    async fn close_token_account(
+        &mut self,
+        account: &Pubkey,
+        destination: &Pubkey,
+        authority: &Keypair,
+    ) -> Result<(), TestError> {
+        let (token_program, _) =
+            get_token_state::<spl_token_2022_interface::state::Account>(self, *account).await?;
+        let ix = token_instruction::close_account(
+            &token_program,
+            account,
+            destination,
+            &authority.pubkey(),
+            &[],
+        )?;
+        self.sign_send_instructions(&[ix], &[authority]).await
+    }
+
+    async fn wrap_sol(&mut self, owner: &Keypair, lamports: u64) -> Result<Pubkey, TestError> {
+        wrap_sol_with_program(self, &spl_token::ID, owner, lamports).await
+    }
+
+    async fn wrap_sol_2022(&mut self, owner: &Keypair, lamports: u64) -> Result<Pubkey, TestError> {
+        wrap_sol_with_program(self, &spl_token_2022_interface::ID, owner, lamports).await
+    }
+
+    async fn get_account_data_borsh<T: BorshDeserialize>(
+        &mut self,
+        key: Pubkey,
+        tag: u64,
+    ) -> Result<T, TestError> {
+        let data = get_tagged_account_data(self, key, tag).await?;
+        // Trailing bytes are allowed since accounts are often allocated with some padding
+        T::deserialize(&mut data.as_slice()).map_err(|_| ProgramError::InvalidAccountData.into())
+    }
+
+    async fn get_account_data_pod<T: Pod>(
+        &mut self,
+        key: Pubkey,
+        tag: u64,
+    ) -> Result<T, TestError> {
+        let data = get_tagged_account_data(self, key, tag).await?;
+        data.get(..std::mem::size_of::<T>())
+            .and_then(|d| bytemuck::try_pod_read_unaligned(d).ok())
+            .ok_or_else(|| ProgramError::InvalidAccountData.into())
     }
 
     async fn get_current_timestamp(&mut self) -> Result<i64, TestError> {
@@ -161,4 +924,473 @@ impl ProgramTestContextExt for ProgramTestContext {
             .await?;
         Ok(account_keypair.pubkey())
     }
+
+    async fn set_mock_oracle(
+        &mut self,
+        key: &Pubkey,
+        oracle: &MockOracle,
+    ) -> Result<(), TestError> {
+        let data = oracle.to_account_data();
+        let lamports = Rent::default().minimum_balance(data.len());
+        let account = Account {
+            lamports,
+            data,
+            owner: MOCK_ORACLE_OWNER,
+            ..Account::default()
+        };
+        self.set_account(key, &account.into());
+        Ok(())
+    }
+
+    async fn set_mock_oracle_price(
+        &mut self,
+        key: &Pubkey,
+        price: i64,
+        exponent: i32,
+    ) -> Result<(), TestError> {
+        let clock = self.banks_client.get_sysvar::<Clock>().await?;
+        let mut oracle = MockOracle::new(price, exponent, clock.unix_timestamp);
+        oracle.slot = clock.slot;
+        self.set_mock_oracle(key, &oracle).await
+    }
+
+    async fn set_pyth_price_v2(
+        &mut self,
+        feed: impl Into<PythFeed> + Send,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Result<Pubkey, TestError> {
+        let feed = feed.into();
+        let clock = self.banks_client.get_sysvar::<Clock>().await?;
+        let data =
+            price_update_v2_data(&feed.feed_id(), price, expo, conf, publish_time, clock.slot);
+        let account = Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: PYTH_RECEIVER,
+            ..Account::default()
+        };
+        let key = feed.account_key();
+        self.set_account(&key, &account.into());
+        Ok(key)
+    }
+
+    async fn set_pyth_price_legacy(
+        &mut self,
+        key: &Pubkey,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Result<(), TestError> {
+        let clock = self.banks_client.get_sysvar::<Clock>().await?;
+        let data = legacy_price_account_data(price, expo, conf, publish_time, clock.slot);
+        let account = Account {
+            lamports: Rent::default().minimum_balance(data.len()),
+            data,
+            owner: PYTH_ORACLE_PROGRAM,
+            ..Account::default()
+        };
+        self.set_account(key, &account.into());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bonfida_utils::oracle::PriceOracle;
+    use solana_program::{
+        account_info::{AccountInfo, IntoAccountInfo},
+        entrypoint::ProgramResult,
+        instruction::AccountMeta,
+        program::set_return_data,
+        sysvar,
+    };
+    use solana_program_test::{processor, ProgramTest};
+
+    use crate::ProgramTestExt;
+
+    const CLOCK_PROGRAM: Pubkey = Pubkey::new_from_array([7; 32]);
+
+    /// Returns the clock observed by the program, given as its sysvar account
+    fn process_clock(_: &Pubkey, accounts: &[AccountInfo], _: &[u8]) -> ProgramResult {
+        set_return_data(&accounts[0].data.borrow());
+        Ok(())
+    }
+
+    async fn start() -> ProgramTestContext {
+        ProgramTest::new("clock", CLOCK_PROGRAM, processor!(process_clock))
+            .start_with_context()
+            .await
+    }
+
+    /// The data of the instruction makes its transactions unique when the blockhash is the same
+    async fn observed_clock(context: &mut ProgramTestContext, nonce: u8) -> Clock {
+        let ix = Instruction::new_with_bytes(
+            CLOCK_PROGRAM,
+            &[nonce],
+            vec![AccountMeta::new_readonly(sysvar::clock::ID, false)],
+        );
+        let result = context
+            .sign_send_instructions_with_metadata(&[ix], &[])
+            .await
+            .unwrap();
+        result.result.unwrap();
+        // The sysvar data is the fields of the clock, in order
+        let data = result.return_data.unwrap().data;
+        let field = |i: usize| data[8 * i..8 * (i + 1)].try_into().unwrap();
+        Clock {
+            slot: u64::from_le_bytes(field(0)),
+            epoch_start_timestamp: i64::from_le_bytes(field(1)),
+            epoch: u64::from_le_bytes(field(2)),
+            leader_schedule_epoch: u64::from_le_bytes(field(3)),
+            unix_timestamp: i64::from_le_bytes(field(4)),
+        }
+    }
+
+    fn assert_consistent(context: &ProgramTestContext, clock: &Clock) {
+        let epoch_schedule = &context.genesis_config().epoch_schedule;
+        assert_eq!(clock.epoch, epoch_schedule.get_epoch(clock.slot));
+        assert_eq!(
+            clock.leader_schedule_epoch,
+            epoch_schedule.get_leader_schedule_epoch(clock.slot)
+        );
+        assert!(clock.epoch_start_timestamp <= clock.unix_timestamp);
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_advance_seconds() {
+        let mut context = start().await;
+        let before = observed_clock(&mut context, 0).await;
+        let clock = context.advance_seconds(3_600).await.unwrap();
+        let observed = observed_clock(&mut context, 1).await;
+        assert_eq!(observed, clock);
+        assert_eq!(observed.unix_timestamp, before.unix_timestamp + 3_600);
+        assert_eq!(
+            observed.slot,
+            before.slot + seconds_to_slots(&context, 3_600)
+        );
+        assert_consistent(&context, &observed);
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_advance_slots() {
+        let mut context = start().await;
+        let before = observed_clock(&mut context, 0).await;
+        let clock = context.advance_slots(1_000).await.unwrap();
+        let observed = observed_clock(&mut context, 1).await;
+        assert_eq!(observed, clock);
+        assert_eq!(observed.slot, before.slot + 1_000);
+        assert_eq!(
+            observed.unix_timestamp,
+            before.unix_timestamp + slots_to_seconds(&context, 1_000)
+        );
+        assert_consistent(&context, &observed);
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_advance_to_epoch() {
+        let mut context = start().await;
+        let before = observed_clock(&mut context, 0).await;
+        let clock = context.advance_to_epoch(5).await.unwrap();
+        let observed = observed_clock(&mut context, 1).await;
+        assert_eq!(observed, clock);
+        let first_slot = context
+            .genesis_config()
+            .epoch_schedule
+            .get_first_slot_in_epoch(5);
+        assert_eq!(observed.slot, first_slot);
+        assert_eq!(observed.epoch, 5);
+        assert_eq!(
+            observed.unix_timestamp,
+            before.unix_timestamp + slots_to_seconds(&context, first_slot - before.slot)
+        );
+        assert_eq!(observed.epoch_start_timestamp, observed.unix_timestamp);
+        assert_consistent(&context, &observed);
+
+        assert!(context.advance_to_epoch(5).await.is_err());
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_warp_to_timestamp() {
+        let mut context = start().await;
+        let before = observed_clock(&mut context, 0).await;
+        let timestamp = before.unix_timestamp + 86_400;
+        context.warp_to_timestamp(timestamp).await.unwrap();
+        let observed = observed_clock(&mut context, 1).await;
+        assert_eq!(observed.unix_timestamp, timestamp);
+        assert_eq!(context.get_current_timestamp().await.unwrap(), timestamp);
+        assert_consistent(&context, &observed);
+
+        assert!(matches!(
+            context.warp_to_timestamp(timestamp - 1).await,
+            Err(TestError::InvalidTimestampForWarp)
+        ));
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_freeze_clock() {
+        let mut context = start().await;
+        let before = observed_clock(&mut context, 0).await;
+        let observed = {
+            let mut frozen = context.freeze_clock();
+
+            let clock = frozen.advance_slots(500).await.unwrap();
+            let observed = observed_clock(&mut frozen, 1).await;
+            assert_eq!(observed, clock);
+            assert_eq!(observed.slot, before.slot + 500);
+            assert_eq!(observed.unix_timestamp, before.unix_timestamp);
+
+            frozen.advance_seconds(60).await.unwrap();
+            let observed = observed_clock(&mut frozen, 2).await;
+            assert_eq!(observed.slot, before.slot + 500);
+            assert_eq!(observed.unix_timestamp, before.unix_timestamp + 60);
+            assert_consistent(&frozen, &observed);
+
+            let clock = frozen.advance_to_epoch(5).await.unwrap();
+            let observed = observed_clock(&mut frozen, 3).await;
+            assert_eq!(observed, clock);
+            assert_eq!(observed.epoch, 5);
+            assert_eq!(observed.unix_timestamp, before.unix_timestamp + 60);
+            assert_eq!(observed.epoch_start_timestamp, observed.unix_timestamp);
+            assert_consistent(&frozen, &observed);
+            observed
+        };
+
+        // Once the frozen clock is dropped, the slots and the timestamp move together again
+        context.advance_slots(500).await.unwrap();
+        let unfrozen = observed_clock(&mut context, 4).await;
+        assert_eq!(unfrozen.slot, observed.slot + 500);
+        assert_eq!(
+            unfrozen.unix_timestamp,
+            before.unix_timestamp + 60 + slots_to_seconds(&context, 500)
+        );
+    }
+
+    /// Runs every token operation with the mints and accounts of `token_program`
+    async fn check_token_operations(token_program: Pubkey) {
+        let mut context = ProgramTest::default().start_with_context().await;
+        let is_2022 = token_program == spl_token_2022_interface::ID;
+        let authority = Keypair::new();
+        let (alice, bob) = (Keypair::new(), Keypair::new());
+        let mint = if is_2022 {
+            context
+                .create_mint_2022(&authority.pubkey(), Some(&authority.pubkey()), 6)
+                .await
+        } else {
+            context
+                .create_mint(&authority.pubkey(), Some(&authority.pubkey()), 6)
+                .await
+        }
+        .unwrap();
+        let owners = [alice.pubkey(), bob.pubkey()];
+        let accounts = if is_2022 {
+            context.initialize_token_accounts_2022(mint, &owners).await
+        } else {
+            context.initialize_token_accounts(mint, &owners).await
+        }
+        .unwrap();
+        let (a, b) = (accounts[0], accounts[1]);
+
+        context
+            .mint_tokens(&authority, &mint, &a, 1_000)
+            .await
+            .unwrap();
+        context.transfer_tokens(&a, &b, &alice, 300).await.unwrap();
+        assert_eq!(context.get_token_account_2022(a).await.unwrap().amount, 700);
+        assert_eq!(context.get_token_account_2022(b).await.unwrap().amount, 300);
+
+        let delegate = Keypair::new();
+        context
+            .approve_tokens(&a, &delegate.pubkey(), &alice, 100)
+            .await
+            .unwrap();
+        context.burn_tokens(&a, &delegate, 60).await.unwrap();
+        let account = context.get_token_account_2022(a).await.unwrap();
+        assert_eq!(account.amount, 640);
+        assert_eq!(account.delegated_amount, 40);
+
+        context.freeze_token_account(&b, &authority).await.unwrap();
+        assert!(context.transfer_tokens(&b, &a, &bob, 1).await.is_err());
+        context.thaw_token_account(&b, &authority).await.unwrap();
+        context.burn_tokens(&b, &bob, 300).await.unwrap();
+        context
+            .close_token_account(&b, &bob.pubkey(), &bob)
+            .await
+            .unwrap();
+        assert!(context.banks_client.get_account(b).await.unwrap().is_none());
+
+        let mint_state = context.get_mint_2022(mint).await.unwrap();
+        assert_eq!(mint_state.supply, 640);
+        assert_eq!(mint_state.decimals, 6);
+        if is_2022 {
+            assert!(matches!(
+                context.get_mint(mint).await,
+                Err(TestError::InvalidTokenAccount)
+            ));
+        } else {
+            assert_eq!(context.get_mint(mint).await.unwrap().supply, 640);
+        }
+
+        context
+            .banks_client
+            .process_transaction(Transaction::new_signed_with_payer(
+                &[system_instruction::transfer(
+                    &context.payer.pubkey(),
+                    &alice.pubkey(),
+                    1_000_000_000,
+                )],
+                Some(&context.payer.pubkey()),
+                &[&context.payer],
+                context.last_blockhash,
+            ))
+            .await
+            .unwrap();
+        let wrapped = if is_2022 {
+            context.wrap_sol_2022(&alice, 500_000_000).await
+        } else {
+            context.wrap_sol(&alice, 500_000_000).await
+        }
+        .unwrap();
+        let account = context.get_token_account_2022(wrapped).await.unwrap();
+        assert_eq!(account.amount, 500_000_000);
+        assert!(account.is_native());
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_token_operations() {
+        check_token_operations(spl_token::ID).await;
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_token_operations_2022() {
+        check_token_operations(spl_token_2022_interface::ID).await;
+    }
+
+    #[derive(borsh::BorshSerialize, BorshDeserialize, Debug, PartialEq)]
+    struct ExampleState {
+        owner: Pubkey,
+        values: Vec<u32>,
+    }
+
+    #[derive(Clone, Copy, bytemuck::Zeroable, Pod, Debug, PartialEq)]
+    #[repr(C)]
+    struct ExampleStateCast {
+        owner: Pubkey,
+        value: u64,
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_get_account_data() {
+        let mut program_test = ProgramTest::default();
+        let owner = Pubkey::new_unique();
+        let (borsh_key, pod_key) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let borsh_state = ExampleState {
+            owner,
+            values: vec![1, 2, 3],
+        };
+        let mut data = 1u64.to_le_bytes().to_vec();
+        data.extend(borsh::to_vec(&borsh_state).unwrap());
+        data.resize(100, 0);
+        program_test.add_account(
+            borsh_key,
+            Account {
+                lamports: 1_000_000,
+                data,
+                owner: CLOCK_PROGRAM,
+                ..Account::default()
+            },
+        );
+        let pod_state = ExampleStateCast { owner, value: 42 };
+        let mut data = 2u64.to_le_bytes().to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(&pod_state));
+        program_test.add_account(
+            pod_key,
+            Account {
+                lamports: 1_000_000,
+                data,
+                owner: CLOCK_PROGRAM,
+                ..Account::default()
+            },
+        );
+        let mut context = program_test.start_with_context().await;
+
+        let state = context
+            .get_account_data_borsh::<ExampleState>(borsh_key, 1)
+            .await
+            .unwrap();
+        assert_eq!(state, borsh_state);
+        let state = context
+            .get_account_data_pod::<ExampleStateCast>(pod_key, 2)
+            .await
+            .unwrap();
+        assert_eq!(state, pod_state);
+
+        assert!(matches!(
+            context
+                .get_account_data_borsh::<ExampleState>(borsh_key, 2)
+                .await,
+            Err(TestError::InvalidAccountTag)
+        ));
+        assert!(matches!(
+            context
+                .get_account_data_pod::<ExampleStateCast>(pod_key, 1)
+                .await,
+            Err(TestError::InvalidAccountTag)
+        ));
+        assert!(context
+            .get_account_data_pod::<ExampleStateCast>(borsh_key, 1)
+            .await
+            .is_ok());
+        assert!(matches!(
+            context
+                .get_account_data_borsh::<ExampleState>(pod_key, 2)
+                .await,
+            Err(TestError::ProgramError(ProgramError::InvalidAccountData))
+        ));
+    }
+
+    async fn read_mock_oracle(context: &mut ProgramTestContext, key: Pubkey) -> MockOracle {
+        let mut account = context
+            .banks_client
+            .get_account(key)
+            .await
+            .unwrap()
+            .unwrap();
+        let account_info = (&key, &mut account).into_account_info();
+        assert!(MockOracle::is_mock_account_info(&account_info));
+        MockOracle::from_account_info(&account_info).unwrap()
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_mock_oracle() {
+        let mut program_test = ProgramTest::default();
+        let mut oracle = MockOracle::new(14_845_000_000, -8, 1_700_000_000);
+        oracle.ema_price = 14_000_000_000;
+        let key = program_test.add_mock_oracle(None, &oracle);
+        let mut context = program_test.start_with_context().await;
+        assert_eq!(read_mock_oracle(&mut context, key).await, oracle);
+
+        let clock = context.advance_slots(100).await.unwrap();
+        context
+            .set_mock_oracle_price(&key, 15_000_000_000, -8)
+            .await
+            .unwrap();
+        let price = read_mock_oracle(&mut context, key)
+            .await
+            .price_no_older_than(&clock, 0)
+            .unwrap();
+        assert_eq!(price.price, 15_000_000_000);
+        assert_eq!(price.exponent, -8);
+        assert_eq!(price.publish_time, clock.unix_timestamp);
+        assert_eq!(price.slot, clock.slot);
+        assert_eq!(price.to_fp32(9, 6), Ok((150u128 << 32) as u64 / 1_000));
+
+        let other = Pubkey::new_unique();
+        context.set_mock_oracle(&other, &oracle).await.unwrap();
+        assert_eq!(read_mock_oracle(&mut context, other).await, oracle);
+    }
 }
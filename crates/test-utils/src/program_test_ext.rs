@@ -1,7 +1,17 @@
-use solana_program::{program_pack::Pack, pubkey::Pubkey};
+use bonfida_utils::{
+    oracle::{MockOracle, MOCK_ORACLE_OWNER},
+    pyth::PYTH_RECEIVER,
+};
+use solana_program::{program_pack::Pack, pubkey::Pubkey, rent::Rent};
 use solana_program_test::ProgramTest;
 use solana_sdk::account::Account;
 use spl_token::state::Mint;
+use std::path::Path;
+
+use crate::{
+    fixtures::{read_fixtures_dir, FixtureRewrites},
+    pyth::{legacy_price_account_data, price_update_v2_data, PythFeed, PYTH_ORACLE_PROGRAM},
+};
 
 pub trait ProgramTestExt {
     fn add_mint(
@@ -12,6 +22,42 @@ pub trait ProgramTestExt {
     ) -> (Pubkey, Mint);
 
     fn add_account_with_lamports(&mut self, key: Pubkey, lamports: u64);
+
+    /// Adds a mock oracle account, see [`bonfida_utils::oracle`] for the layout
+    fn add_mock_oracle(&mut self, key: Option<Pubkey>, oracle: &MockOracle) -> Pubkey;
+
+    /// Adds a Pyth pull oracle price update at the account of `feed` pushed by the default
+    /// sponsor, see [`crate::pyth::price_update_v2_data`]
+    fn add_pyth_price_v2(
+        &mut self,
+        feed: impl Into<PythFeed>,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Pubkey;
+
+    /// Adds a legacy Pyth price account, see [`crate::pyth::legacy_price_account_data`]
+    fn add_pyth_price_legacy(
+        &mut self,
+        key: Option<Pubkey>,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Pubkey;
+
+    /// Adds the accounts and programs of a fixtures directory, see [`crate::fixtures`], and
+    /// returns their keys
+    fn add_accounts_from_dir(&mut self, path: impl AsRef<Path>) -> Vec<Pubkey>;
+
+    /// Same as [`ProgramTestExt::add_accounts_from_dir`], rewriting the keys and owners of the
+    /// fixtures
+    fn add_accounts_from_dir_with(
+        &mut self,
+        path: impl AsRef<Path>,
+        rewrites: &FixtureRewrites,
+    ) -> Vec<Pubkey>;
 }
 
 impl ProgramTestExt for ProgramTest {
@@ -53,4 +99,85 @@ impl ProgramTestExt for ProgramTest {
             },
         );
     }
+
+    fn add_mock_oracle(&mut self, key: Option<Pubkey>, oracle: &MockOracle) -> Pubkey {
+        let address = key.unwrap_or_else(Pubkey::new_unique);
+        let data = oracle.to_account_data();
+        self.add_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: MOCK_ORACLE_OWNER,
+                executable: false,
+                ..Account::default()
+            },
+        );
+        address
+    }
+
+    fn add_pyth_price_v2(
+        &mut self,
+        feed: impl Into<PythFeed>,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Pubkey {
+        let feed = feed.into();
+        let address = feed.account_key();
+        let data = price_update_v2_data(&feed.feed_id(), price, expo, conf, publish_time, 0);
+        self.add_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: PYTH_RECEIVER,
+                executable: false,
+                ..Account::default()
+            },
+        );
+        address
+    }
+
+    fn add_pyth_price_legacy(
+        &mut self,
+        key: Option<Pubkey>,
+        price: i64,
+        expo: i32,
+        conf: u64,
+        publish_time: i64,
+    ) -> Pubkey {
+        let address = key.unwrap_or_else(Pubkey::new_unique);
+        let data = legacy_price_account_data(price, expo, conf, publish_time, 0);
+        self.add_account(
+            address,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: PYTH_ORACLE_PROGRAM,
+                executable: false,
+                ..Account::default()
+            },
+        );
+        address
+    }
+
+    fn add_accounts_from_dir(&mut self, path: impl AsRef<Path>) -> Vec<Pubkey> {
+        self.add_accounts_from_dir_with(path, &FixtureRewrites::default())
+    }
+
+    fn add_accounts_from_dir_with(
+        &mut self,
+        path: impl AsRef<Path>,
+        rewrites: &FixtureRewrites,
+    ) -> Vec<Pubkey> {
+        read_fixtures_dir(path, rewrites)
+            .into_iter()
+            .map(|(key, account)| {
+                self.add_account(key, account);
+                key
+            })
+            .collect()
+    }
 }
@@ -0,0 +1,244 @@
+//! Pyth account fixtures, in the layouts read by [`bonfida_utils::pyth`].
+use bonfida_utils::{
+    pyth::{
+        get_pyth_feed_account_key,
+        utils::{FeedId, PriceFeedMessage, PriceUpdateV2, VerificationLevel},
+        PRICE_FEED_DISCRIMATOR,
+    },
+    tokens::SupportedToken,
+};
+use pyth_sdk_solana::state::{
+    AccountType, PriceStatus, Rational, SolanaPriceAccount, MAGIC, VERSION_2,
+};
+use solana_program::{pubkey, pubkey::Pubkey};
+
+/// The legacy Pyth oracle program on mainnet
+pub const PYTH_ORACLE_PROGRAM: Pubkey = pubkey!("FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+
+/// A Pyth pull oracle feed, given by a supported token or by its feed id
+#[derive(Debug, Clone, Copy)]
+pub enum PythFeed {
+    Token(SupportedToken),
+    /// A feed whose price update account is on shard 0
+    FeedId(FeedId),
+}
+
+impl From<SupportedToken> for PythFeed {
+    fn from(token: SupportedToken) -> Self {
+        Self::Token(token)
+    }
+}
+
+impl From<FeedId> for PythFeed {
+    fn from(feed_id: FeedId) -> Self {
+        Self::FeedId(feed_id)
+    }
+}
+
+impl PythFeed {
+    pub fn feed_id(self) -> FeedId {
+        match self {
+            Self::Token(token) => token.price_feed(),
+            Self::FeedId(feed_id) => feed_id,
+        }
+    }
+
+    /// Returns the price update account of the feed pushed by the default Pyth sponsor
+    pub fn account_key(self) -> Pubkey {
+        match self {
+            Self::Token(token) => token.price_feed_account_key(),
+            Self::FeedId(feed_id) => get_pyth_feed_account_key(0, &feed_id),
+        }
+    }
+}
+
+/// Returns the data of a fully verified `PriceUpdateV2` account, the EMA price being equal to
+/// the spot price, padded to the size of the receiver accounts
+pub fn price_update_v2_data(
+    feed_id: &FeedId,
+    price: i64,
+    expo: i32,
+    conf: u64,
+    publish_time: i64,
+    posted_slot: u64,
+) -> Vec<u8> {
+    let update = PriceUpdateV2 {
+        write_authority: Pubkey::default(),
+        verification_level: VerificationLevel::Full,
+        price_message: PriceFeedMessage {
+            feed_id: *feed_id,
+            price,
+            conf,
+            exponent: expo,
+            publish_time,
+            prev_publish_time: publish_time,
+            ema_price: price,
+            ema_conf: conf,
+        },
+        posted_slot,
+    };
+    let mut data = PRICE_FEED_DISCRIMATOR.to_vec();
+    borsh::to_writer(&mut data, &update).unwrap();
+    data.resize(PriceUpdateV2::LEN, 0);
+    data
+}
+
+/// Returns the data of a trading legacy `SolanaPriceAccount`, the EMA price being equal to the
+/// spot price
+pub fn legacy_price_account_data(
+    price: i64,
+    expo: i32,
+    conf: u64,
+    publish_time: i64,
+    slot: u64,
+) -> Vec<u8> {
+    let mut account = SolanaPriceAccount {
+        magic: MAGIC,
+        ver: VERSION_2,
+        atype: AccountType::Price as u32,
+        size: std::mem::size_of::<SolanaPriceAccount>() as u32,
+        expo,
+        last_slot: slot,
+        valid_slot: slot,
+        ema_price: Rational {
+            val: price,
+            numer: price,
+            denom: 1,
+        },
+        ema_conf: Rational {
+            val: conf as i64,
+            numer: conf as i64,
+            denom: 1,
+        },
+        timestamp: publish_time,
+        prev_slot: slot,
+        prev_price: price,
+        prev_conf: conf,
+        prev_timestamp: publish_time,
+        ..Default::default()
+    };
+    account.agg.price = price;
+    account.agg.conf = conf;
+    account.agg.status = PriceStatus::Trading;
+    account.agg.pub_slot = slot;
+    bytemuck::bytes_of(&account).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bonfida_utils::pyth::{get_oracle_price_fp32_v2, parse_price_v2, PYTH_RECEIVER};
+    use pyth_sdk_solana::state::load_price_account;
+    use solana_program::{account_info::IntoAccountInfo, clock::Clock};
+    use solana_program_test::{ProgramTest, ProgramTestContext};
+    use solana_sdk::account::Account;
+
+    use crate::{ProgramTestContextExt, ProgramTestExt};
+
+    #[test]
+    fn test_price_update_v2_data() {
+        let feed_id = SupportedToken::Sol.price_feed();
+        let data = price_update_v2_data(&feed_id, 14_845_000_000, -8, 1_000_000, 1_700_000_000, 42);
+        assert_eq!(data.len(), PriceUpdateV2::LEN);
+
+        let update = parse_price_v2(&data).unwrap();
+        assert_eq!(update.write_authority, Pubkey::default());
+        assert!(update.verification_level.gte(VerificationLevel::Full));
+        assert_eq!(update.posted_slot, 42);
+        let price = update.get_price_unchecked(&feed_id).unwrap();
+        assert_eq!(price.price, 14_845_000_000);
+        assert_eq!(price.conf, 1_000_000);
+        assert_eq!(price.exponent, -8);
+        assert_eq!(price.publish_time, 1_700_000_000);
+        assert_eq!(update.price_message.ema_price, 14_845_000_000);
+        assert_eq!(update.price_message.ema_conf, 1_000_000);
+        assert_eq!(update.price_message.prev_publish_time, 1_700_000_000);
+    }
+
+    #[test]
+    fn test_legacy_price_account_data() {
+        let data = legacy_price_account_data(14_845_000_000, -8, 1_000_000, 1_700_000_000, 42);
+        let account: &SolanaPriceAccount = load_price_account(&data).unwrap();
+        assert_eq!(account.expo, -8);
+        assert_eq!(account.agg.price, 14_845_000_000);
+        assert_eq!(account.agg.conf, 1_000_000);
+        assert_eq!(account.agg.status, PriceStatus::Trading);
+        assert_eq!(account.agg.pub_slot, 42);
+        assert_eq!(account.timestamp, 1_700_000_000);
+        assert_eq!(account.ema_price.val, 14_845_000_000);
+
+        let price = account
+            .to_price_feed(&Pubkey::default())
+            .get_price_unchecked();
+        assert_eq!(price.price, 14_845_000_000);
+        assert_eq!(price.conf, 1_000_000);
+        assert_eq!(price.publish_time, 1_700_000_000);
+    }
+
+    async fn oracle_price(
+        context: &mut ProgramTestContext,
+        key: Pubkey,
+        clock: &Clock,
+    ) -> Result<u64, solana_program::program_error::ProgramError> {
+        let mut account: Account = context
+            .banks_client
+            .get_account(key)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.owner, PYTH_RECEIVER);
+        assert_eq!(account.data.len(), PriceUpdateV2::LEN);
+        let account_info = (&key, &mut account).into_account_info();
+        get_oracle_price_fp32_v2(&SupportedToken::Sol.mint(), &account_info, 9, 6, clock, 60)
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_pyth_price_v2() {
+        let mut program_test = ProgramTest::default();
+        let key = program_test.add_pyth_price_v2(
+            SupportedToken::Sol,
+            14_845_000_000,
+            -8,
+            1_000_000,
+            1_700_000_000,
+        );
+        assert_eq!(key, SupportedToken::Sol.price_feed_account_key());
+        let mut context = program_test.start_with_context().await;
+
+        let clock = Clock {
+            unix_timestamp: 1_700_000_030,
+            ..Clock::default()
+        };
+        let price = oracle_price(&mut context, key, &clock).await.unwrap();
+        assert_eq!(price, (148_450u128 << 32) as u64 / 1_000_000);
+
+        let key = context
+            .set_pyth_price_v2(
+                SupportedToken::Sol,
+                15_000_000_000,
+                -8,
+                1_000_000,
+                1_700_000_100,
+            )
+            .await
+            .unwrap();
+        assert_eq!(key, SupportedToken::Sol.price_feed_account_key());
+        let clock = Clock {
+            unix_timestamp: 1_700_000_130,
+            ..Clock::default()
+        };
+        // The previous update is overwritten
+        let price = oracle_price(&mut context, key, &clock).await.unwrap();
+        assert_eq!(price, (150u128 << 32) as u64 / 1_000);
+
+        // The staleness is checked against the new publish time
+        let clock = Clock {
+            unix_timestamp: 1_700_000_200,
+            ..Clock::default()
+        };
+        assert_eq!(
+            oracle_price(&mut context, key, &clock).await,
+            Err(bonfida_utils::pyth::utils::GetPriceError::PriceTooOld.into())
+        );
+    }
+}
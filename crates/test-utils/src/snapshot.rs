@@ -0,0 +1,888 @@
+//! Snapshots of accounts taken around a transaction, and the structured diff between them.
+use borsh::BorshDeserialize;
+use bytemuck::Pod;
+use solana_program::{program_option::COption, program_pack::Pack, pubkey::Pubkey};
+use solana_sdk::account::Account;
+use spl_token_2022_interface::{
+    extension::{BaseState, StateWithExtensions},
+    state::{Account as TokenAccount, Mint},
+};
+use std::{
+    fmt::{self, Debug, Display},
+    marker::PhantomData,
+};
+
+/// Decodes the state of accounts into named fields, which are diffed one by one
+pub trait AccountDecoder: Send + Sync {
+    /// Returns the fields of the account, or `None` if the decoder does not handle it
+    fn decode(&self, account: &Account) -> Option<Vec<(String, String)>>;
+}
+
+/// Decodes the base state of SPL token and Token-2022 accounts and mints, always used after the
+/// decoders given to a diff
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SplTokenDecoder;
+
+fn coption_to_string<T: Display>(option: &COption<T>) -> String {
+    match option {
+        COption::Some(value) => value.to_string(),
+        COption::None => "None".to_owned(),
+    }
+}
+
+fn fields<const N: usize>(fields: [(&str, String); N]) -> Vec<(String, String)> {
+    fields
+        .into_iter()
+        .map(|(name, value)| (name.to_owned(), value))
+        .collect()
+}
+
+fn is_token_program(key: &Pubkey) -> bool {
+    *key == spl_token::ID || *key == spl_token_2022_interface::ID
+}
+
+/// Unpacks the base state of an account of either token program
+fn token_state<S: BaseState + Pack>(account: &Account) -> Option<S> {
+    if !is_token_program(&account.owner) {
+        return None;
+    }
+    Some(StateWithExtensions::<S>::unpack(&account.data).ok()?.base)
+}
+
+impl AccountDecoder for SplTokenDecoder {
+    fn decode(&self, account: &Account) -> Option<Vec<(String, String)>> {
+        if let Some(a) = token_state::<TokenAccount>(account) {
+            return Some(fields([
+                ("mint", a.mint.to_string()),
+                ("owner", a.owner.to_string()),
+                ("amount", a.amount.to_string()),
+                ("delegate", coption_to_string(&a.delegate)),
+                ("state", format!("{:?}", a.state)),
+                ("is_native", coption_to_string(&a.is_native)),
+                ("delegated_amount", a.delegated_amount.to_string()),
+                ("close_authority", coption_to_string(&a.close_authority)),
+            ]));
+        }
+        let m = token_state::<Mint>(account)?;
+        Some(fields([
+            ("mint_authority", coption_to_string(&m.mint_authority)),
+            ("supply", m.supply.to_string()),
+            ("decimals", m.decimals.to_string()),
+            ("is_initialized", m.is_initialized.to_string()),
+            ("freeze_authority", coption_to_string(&m.freeze_authority)),
+        ]))
+    }
+}
+
+/// Splits `s` at the `, ` separators which are outside of any nested value or literal
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            // String and char literals, escaped by `Debug`
+            '"' | '\'' => {
+                while let Some((_, d)) = chars.next() {
+                    if d == '\\' {
+                        chars.next();
+                    } else if d == c {
+                        break;
+                    }
+                }
+            }
+            ',' if depth == 0 && chars.peek().map(|(_, d)| *d) == Some(' ') => {
+                parts.push(&s[start..i]);
+                start = i + 2;
+            }
+            _ => (),
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// Splits the `Debug` output of a struct into its top level fields, named after the struct
+/// fields or after their position for tuple structs, nested values keeping their compact
+/// `Debug` format
+pub fn debug_fields<T: Debug>(value: &T) -> Vec<(String, String)> {
+    let compact = format!("{:?}", value);
+    let name_len = compact
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(compact.len());
+    let rest = &compact[name_len..];
+    if name_len > 0 {
+        if let Some(body) = rest.strip_prefix(" { ").and_then(|r| r.strip_suffix(" }")) {
+            let fields = split_top_level(body)
+                .into_iter()
+                .map(|field| field.split_once(": "))
+                .collect::<Option<Vec<_>>>();
+            if let Some(fields) = fields {
+                return fields
+                    .into_iter()
+                    .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                    .collect();
+            }
+        } else if let Some(body) = rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')) {
+            return split_top_level(body)
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (i.to_string(), value.to_owned()))
+                .collect();
+        }
+    }
+    vec![("value".to_owned(), compact)]
+}
+
+/// How the state of a [`StateDecoder`] is read from the account data
+pub trait StateFormat<T> {
+    /// Reads the state at the start of `data`, which can hold trailing bytes
+    fn read(data: &[u8]) -> Option<T>;
+}
+
+/// Reads Borsh states
+#[derive(Debug, Clone, Copy)]
+pub struct BorshFormat;
+
+impl<T: BorshDeserialize> StateFormat<T> for BorshFormat {
+    fn read(mut data: &[u8]) -> Option<T> {
+        // Trailing bytes are allowed since accounts are often allocated with some padding
+        T::deserialize(&mut data).ok()
+    }
+}
+
+/// Reads `Pod` states
+#[derive(Debug, Clone, Copy)]
+pub struct PodFormat;
+
+impl<T: Pod> StateFormat<T> for PodFormat {
+    fn read(data: &[u8]) -> Option<T> {
+        bytemuck::try_pod_read_unaligned(data.get(..std::mem::size_of::<T>())?).ok()
+    }
+}
+
+/// Decodes the state of the accounts owned by a program, with one field per struct field
+#[derive(Debug, Clone)]
+pub struct StateDecoder<T, F> {
+    program_id: Pubkey,
+    discriminator: Vec<u8>,
+    offset: usize,
+    _state: PhantomData<fn() -> (T, F)>,
+}
+
+/// Decodes the Borsh state of the accounts owned by a program
+pub type BorshDecoder<T> = StateDecoder<T, BorshFormat>;
+
+/// Decodes the `Pod` state of the accounts owned by a program
+pub type PodDecoder<T> = StateDecoder<T, PodFormat>;
+
+impl<T, F: StateFormat<T>> StateDecoder<T, F> {
+    pub fn new(program_id: Pubkey) -> Self {
+        Self {
+            program_id,
+            discriminator: Vec::new(),
+            offset: 0,
+            _state: PhantomData,
+        }
+    }
+
+    /// Only decodes the accounts starting with `discriminator`, the state following it
+    pub fn with_discriminator(mut self, discriminator: &[u8]) -> Self {
+        self.discriminator = discriminator.to_vec();
+        self.offset = discriminator.len();
+        self
+    }
+
+    /// Decodes the state starting at `offset` in the account data
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+}
+
+impl<T: Debug, F: StateFormat<T>> AccountDecoder for StateDecoder<T, F> {
+    fn decode(&self, account: &Account) -> Option<Vec<(String, String)>> {
+        if account.owner != self.program_id || !account.data.starts_with(&self.discriminator) {
+            return None;
+        }
+        let state = F::read(account.data.get(self.offset..)?)?;
+        Some(debug_fields(&state))
+    }
+}
+
+/// The accounts at a given point of a test, `None` for the accounts which do not exist
+#[derive(Debug, Clone, Default)]
+pub struct AccountsSnapshot {
+    pub accounts: Vec<(Pubkey, Option<Account>)>,
+}
+
+impl AccountsSnapshot {
+    pub fn get(&self, key: &Pubkey) -> Option<&Account> {
+        self.accounts
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, account)| account.as_ref())
+    }
+
+    /// Diffs this snapshot with a later snapshot of the same accounts, decoding their state with
+    /// the first of `decoders` handling them and then with [`SplTokenDecoder`]
+    pub fn diff(&self, after: &AccountsSnapshot, decoders: &[&dyn AccountDecoder]) -> SnapshotDiff {
+        let mut keys: Vec<Pubkey> = self.accounts.iter().map(|(k, _)| *k).collect();
+        for (key, _) in &after.accounts {
+            if !keys.contains(key) {
+                keys.push(*key);
+            }
+        }
+        let accounts = keys
+            .into_iter()
+            .map(|key| AccountDiff::new(key, self.get(&key), after.get(&key), decoders))
+            .filter(|diff| !diff.is_empty())
+            .collect();
+        SnapshotDiff { accounts }
+    }
+}
+
+/// A range of account data which changed, the trailing range including any resizing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataChange {
+    pub offset: usize,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+}
+
+/// A decoded field which changed, `None` when the field is only decoded on one side
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// The changes made to an account, missing accounts being diffed as empty system accounts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub key: Pubkey,
+    pub before: Option<Account>,
+    pub after: Option<Account>,
+    pub lamports: Option<(u64, u64)>,
+    pub owner: Option<(Pubkey, Pubkey)>,
+    pub data: Vec<DataChange>,
+    pub fields: Vec<FieldChange>,
+}
+
+fn decode(account: &Account, decoders: &[&dyn AccountDecoder]) -> Vec<(String, String)> {
+    decoders
+        .iter()
+        .copied()
+        .chain(std::iter::once(&SplTokenDecoder as &dyn AccountDecoder))
+        .find_map(|d| d.decode(account))
+        .unwrap_or_default()
+}
+
+fn data_changes(before: &[u8], after: &[u8]) -> Vec<DataChange> {
+    let len = before.len().max(after.len());
+    let mut changes = Vec::new();
+    let mut start = None;
+    for i in 0..=len {
+        let differs = i < len && before.get(i) != after.get(i);
+        match (differs, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                changes.push(DataChange {
+                    offset: s,
+                    before: before.get(s..i.min(before.len())).unwrap_or(&[]).to_vec(),
+                    after: after.get(s..i.min(after.len())).unwrap_or(&[]).to_vec(),
+                });
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    changes
+}
+
+impl AccountDiff {
+    fn new(
+        key: Pubkey,
+        before: Option<&Account>,
+        after: Option<&Account>,
+        decoders: &[&dyn AccountDecoder],
+    ) -> Self {
+        let empty = Account::default();
+        let (b, a) = (before.unwrap_or(&empty), after.unwrap_or(&empty));
+        let before_fields = decode(b, decoders);
+        let after_fields = decode(a, decoders);
+        let mut fields = Vec::new();
+        for (name, value) in &before_fields {
+            let new_value = after_fields.iter().find(|(n, _)| n == name).map(|(_, v)| v);
+            if new_value != Some(value) {
+                fields.push(FieldChange {
+                    name: name.clone(),
+                    before: Some(value.clone()),
+                    after: new_value.cloned(),
+                });
+            }
+        }
+        for (name, value) in &after_fields {
+            if !before_fields.iter().any(|(n, _)| n == name) {
+                fields.push(FieldChange {
+                    name: name.clone(),
+                    before: None,
+                    after: Some(value.clone()),
+                });
+            }
+        }
+        Self {
+            key,
+            before: before.cloned(),
+            after: after.cloned(),
+            lamports: (b.lamports != a.lamports).then_some((b.lamports, a.lamports)),
+            owner: (b.owner != a.owner).then_some((b.owner, a.owner)),
+            data: data_changes(&b.data, &a.data),
+            fields,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.before.is_some() == self.after.is_some()
+            && self.lamports.is_none()
+            && self.owner.is_none()
+            && self.data.is_empty()
+    }
+
+    pub fn is_created(&self) -> bool {
+        self.before.is_none() && self.after.is_some()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.before.is_some() && self.after.is_none()
+    }
+
+    pub fn lamports_delta(&self) -> i128 {
+        self.lamports.map_or(0, |(b, a)| a as i128 - b as i128)
+    }
+
+    pub fn field(&self, name: &str) -> Option<&FieldChange> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+
+    /// Returns the change of balance of an SPL token or Token-2022 account, `None` if it is not
+    /// one
+    pub fn token_amount_delta(&self) -> Option<i128> {
+        let amount = |account: &Option<Account>| match account {
+            Some(a) => token_state::<TokenAccount>(a).map(|a| a.amount),
+            None => Some(0),
+        };
+        Some(amount(&self.after)? as i128 - amount(&self.before)? as i128)
+    }
+}
+
+/// The accounts which changed between two snapshots, in the order they were given
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SnapshotDiff {
+    pub accounts: Vec<AccountDiff>,
+}
+
+impl SnapshotDiff {
+    pub fn get(&self, key: &Pubkey) -> Option<&AccountDiff> {
+        self.accounts.iter().find(|a| &a.key == key)
+    }
+
+    pub fn is_unchanged(&self, key: &Pubkey) -> bool {
+        self.get(key).is_none()
+    }
+
+    pub fn changed_keys(&self) -> Vec<Pubkey> {
+        self.accounts.iter().map(|a| a.key).collect()
+    }
+
+    pub fn lamports_delta(&self, key: &Pubkey) -> i128 {
+        self.get(key).map_or(0, |a| a.lamports_delta())
+    }
+
+    /// Returns the change of balance of a token account, zero if it did not change
+    pub fn token_amount_delta(&self, key: &Pubkey) -> i128 {
+        self.get(key)
+            .and_then(|a| a.token_amount_delta())
+            .unwrap_or(0)
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    if bytes.is_empty() {
+        return write!(f, "-");
+    }
+    bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+}
+
+impl Display for SnapshotDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for account in &self.accounts {
+            let status = if account.is_created() {
+                " (created)"
+            } else if account.is_closed() {
+                " (closed)"
+            } else {
+                ""
+            };
+            writeln!(f, "{}{}", account.key, status)?;
+            if let Some((before, after)) = account.lamports {
+                writeln!(
+                    f,
+                    "    lamports: {} -> {} ({:+})",
+                    before,
+                    after,
+                    account.lamports_delta()
+                )?;
+            }
+            if let Some((before, after)) = account.owner {
+                writeln!(f, "    owner: {} -> {}", before, after)?;
+            }
+            for change in &account.fields {
+                let before = change.before.as_deref().unwrap_or("-");
+                let after = change.after.as_deref().unwrap_or("-");
+                writeln!(f, "    {}: {} -> {}", change.name, before, after)?;
+            }
+            for change in &account.data {
+                let len = change.before.len().max(change.after.len());
+                write!(f, "    data[{}..{}]: ", change.offset, change.offset + len)?;
+                write_hex(f, &change.before)?;
+                write!(f, " -> ")?;
+                write_hex(f, &change.after)?;
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env::TestEnv, ProgramTestContextExt};
+    use borsh::BorshSerialize;
+    use bytemuck::Zeroable;
+    use solana_program::example_mocks::solana_sdk::system_instruction;
+    use solana_program_test::ProgramTest;
+
+    fn change(offset: usize, before: &[u8], after: &[u8]) -> DataChange {
+        DataChange {
+            offset,
+            before: before.to_vec(),
+            after: after.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_data_changes() {
+        assert!(data_changes(&[1, 2, 3], &[1, 2, 3]).is_empty());
+        // Adjacent bytes are merged into a single run
+        assert_eq!(
+            data_changes(&[1, 2, 3, 4, 5, 6], &[1, 9, 9, 4, 5, 7]),
+            vec![change(1, &[2, 3], &[9, 9]), change(5, &[6], &[7])]
+        );
+        // Growing data: the trailing run includes the new bytes
+        assert_eq!(
+            data_changes(&[1, 2, 3], &[1, 2, 4, 5, 6]),
+            vec![change(2, &[3], &[4, 5, 6])]
+        );
+        assert_eq!(
+            data_changes(&[1, 2], &[1, 2, 0, 0]),
+            vec![change(2, &[], &[0, 0])]
+        );
+        // Shrinking data: the trailing run includes the removed bytes
+        assert_eq!(
+            data_changes(&[1, 2, 3, 4], &[9, 2]),
+            vec![change(0, &[1], &[9]), change(2, &[3, 4], &[])]
+        );
+        assert_eq!(data_changes(&[1, 2], &[]), vec![change(0, &[1, 2], &[])]);
+    }
+
+    // The fields of the following structs are only read through `Debug`
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Tricky {
+        name: String,
+        tags: Vec<&'static str>,
+        nested: Nested,
+        quote: char,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Nested {
+        a: u8,
+        b: (u8, u8),
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Wrapper(u64, Option<u8>);
+
+    #[test]
+    fn test_debug_fields() {
+        let value = Tricky {
+            name: "a, ] b: \"c\" }".to_owned(),
+            tags: vec!["[ x", "y, )"],
+            nested: Nested { a: 1, b: (2, 3) },
+            quote: '\'',
+        };
+        assert_eq!(
+            debug_fields(&value),
+            vec![
+                ("name".to_owned(), r#""a, ] b: \"c\" }""#.to_owned()),
+                ("tags".to_owned(), r#"["[ x", "y, )"]"#.to_owned()),
+                ("nested".to_owned(), "Nested { a: 1, b: (2, 3) }".to_owned()),
+                ("quote".to_owned(), r"'\''".to_owned()),
+            ]
+        );
+        assert_eq!(
+            debug_fields(&Wrapper(5, Some(1))),
+            vec![
+                ("0".to_owned(), "5".to_owned()),
+                ("1".to_owned(), "Some(1)".to_owned())
+            ]
+        );
+        assert_eq!(
+            debug_fields(&[1, 2]),
+            vec![("value".to_owned(), "[1, 2]".to_owned())]
+        );
+        assert_eq!(
+            debug_fields(&-3),
+            vec![("value".to_owned(), "-3".to_owned())]
+        );
+    }
+
+    #[derive(Debug, BorshSerialize, BorshDeserialize)]
+    struct BorshState {
+        count: u32,
+        label: String,
+    }
+
+    #[derive(Debug, Clone, Copy, Zeroable, Pod)]
+    #[repr(C)]
+    struct PodState {
+        count: u64,
+        total: u64,
+    }
+
+    fn program_account(owner: Pubkey, data: Vec<u8>) -> Account {
+        Account {
+            lamports: 1_000,
+            data,
+            owner,
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn test_borsh_decoder() {
+        let program_id = Pubkey::new_unique();
+        let state = BorshState {
+            count: 7,
+            label: "x".to_owned(),
+        };
+        let mut data = vec![1, 2];
+        data.extend(borsh::to_vec(&state).unwrap());
+        // Padding following the state
+        data.extend([0; 4]);
+        let account = program_account(program_id, data.clone());
+        let expected = vec![
+            ("count".to_owned(), "7".to_owned()),
+            ("label".to_owned(), "\"x\"".to_owned()),
+        ];
+
+        let decoder = BorshDecoder::<BorshState>::new(program_id).with_discriminator(&[1, 2]);
+        assert_eq!(decoder.decode(&account), Some(expected.clone()));
+        let decoder = BorshDecoder::<BorshState>::new(program_id).with_offset(2);
+        assert_eq!(decoder.decode(&account), Some(expected));
+        assert_eq!(
+            BorshDecoder::<BorshState>::new(program_id)
+                .with_discriminator(&[1, 3])
+                .decode(&account),
+            None
+        );
+        assert_eq!(
+            BorshDecoder::<BorshState>::new(Pubkey::new_unique())
+                .with_discriminator(&[1, 2])
+                .decode(&account),
+            None
+        );
+        // The discriminator is checked even though the state starts elsewhere
+        let decoder = BorshDecoder::<BorshState>::new(program_id)
+            .with_discriminator(&[1])
+            .with_offset(2);
+        assert!(decoder.decode(&account).is_some());
+        let decoder = BorshDecoder::<BorshState>::new(program_id).with_offset(100);
+        assert_eq!(decoder.decode(&account), None);
+    }
+
+    #[test]
+    fn test_pod_decoder() {
+        let program_id = Pubkey::new_unique();
+        let state = PodState {
+            count: 3,
+            total: 40,
+        };
+        // The state is read unaligned
+        let mut data = vec![9; 3];
+        data.extend_from_slice(bytemuck::bytes_of(&state));
+        let account = program_account(program_id, data);
+        let expected = vec![
+            ("count".to_owned(), "3".to_owned()),
+            ("total".to_owned(), "40".to_owned()),
+        ];
+        let decoder = PodDecoder::<PodState>::new(program_id).with_discriminator(&[9; 3]);
+        assert_eq!(decoder.decode(&account), Some(expected.clone()));
+        let decoder = PodDecoder::<PodState>::new(program_id).with_offset(3);
+        assert_eq!(decoder.decode(&account), Some(expected));
+        assert_eq!(
+            PodDecoder::<PodState>::new(program_id)
+                .with_discriminator(&[8])
+                .decode(&account),
+            None
+        );
+        // Not enough data for the state
+        assert_eq!(
+            PodDecoder::<PodState>::new(program_id)
+                .with_offset(4)
+                .decode(&account),
+            None
+        );
+    }
+
+    fn token_account(token_program: Pubkey, amount: u64) -> Account {
+        let state = TokenAccount {
+            mint: Pubkey::new_from_array([1; 32]),
+            owner: Pubkey::new_from_array([2; 32]),
+            amount,
+            state: spl_token_2022_interface::state::AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        let mut data = vec![0; TokenAccount::LEN];
+        state.pack_into_slice(&mut data);
+        Account {
+            lamports: 2_039_280,
+            data,
+            owner: token_program,
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn test_diff_created_and_closed_accounts() {
+        let program_id = Pubkey::new_unique();
+        let (created, closed, unchanged, missing) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let account = program_account(program_id, vec![1, 2]);
+        let before = AccountsSnapshot {
+            accounts: vec![
+                (created, None),
+                (closed, Some(account.clone())),
+                (unchanged, Some(account.clone())),
+                (missing, None),
+            ],
+        };
+        let after = AccountsSnapshot {
+            accounts: vec![
+                (created, Some(account.clone())),
+                (closed, None),
+                (unchanged, Some(account.clone())),
+                (missing, None),
+            ],
+        };
+        let diff = before.diff(&after, &[]);
+        assert_eq!(
+            diff,
+            SnapshotDiff {
+                accounts: vec![
+                    AccountDiff {
+                        key: created,
+                        before: None,
+                        after: Some(account.clone()),
+                        lamports: Some((0, 1_000)),
+                        owner: Some((Pubkey::default(), program_id)),
+                        data: vec![change(0, &[], &[1, 2])],
+                        fields: vec![],
+                    },
+                    AccountDiff {
+                        key: closed,
+                        before: Some(account.clone()),
+                        after: None,
+                        lamports: Some((1_000, 0)),
+                        owner: Some((program_id, Pubkey::default())),
+                        data: vec![change(0, &[1, 2], &[])],
+                        fields: vec![],
+                    },
+                ]
+            }
+        );
+        assert!(diff.get(&created).unwrap().is_created());
+        assert!(diff.get(&closed).unwrap().is_closed());
+        assert!(diff.is_unchanged(&unchanged) && diff.is_unchanged(&missing));
+        assert_eq!(diff.lamports_delta(&closed), -1_000);
+        assert_eq!(
+            diff.to_string(),
+            format!(
+                "{created} (created)\n    lamports: 0 -> 1000 (+1000)\n    owner: {default} -> \
+                 {program_id}\n    data[0..2]: - -> 0102\n{closed} (closed)\n    lamports: 1000 \
+                 -> 0 (-1000)\n    owner: {program_id} -> {default}\n    data[0..2]: 0102 -> -\n",
+                default = Pubkey::default()
+            )
+        );
+
+        // A token account created and closed
+        let key = Pubkey::new_unique();
+        let open = AccountsSnapshot {
+            accounts: vec![(key, Some(token_account(spl_token::ID, 5)))],
+        };
+        let empty = AccountsSnapshot {
+            accounts: vec![(key, None)],
+        };
+        assert_eq!(empty.diff(&open, &[]).token_amount_delta(&key), 5);
+        assert_eq!(open.diff(&empty, &[]).token_amount_delta(&key), -5);
+        let diff = empty.diff(&open, &[]);
+        assert_eq!(
+            diff.accounts[0].field("amount"),
+            Some(&FieldChange {
+                name: "amount".to_owned(),
+                before: None,
+                after: Some("5".to_owned()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_token_2022_accounts() {
+        let key = Pubkey::new_unique();
+        let before = AccountsSnapshot {
+            accounts: vec![(key, Some(token_account(spl_token_2022_interface::ID, 10)))],
+        };
+        let after = AccountsSnapshot {
+            accounts: vec![(key, Some(token_account(spl_token_2022_interface::ID, 4)))],
+        };
+        let diff = before.diff(&after, &[]);
+        assert_eq!(diff.token_amount_delta(&key), -6);
+        assert_eq!(
+            diff.accounts[0].fields,
+            vec![FieldChange {
+                name: "amount".to_owned(),
+                before: Some("10".to_owned()),
+                after: Some("4".to_owned()),
+            }]
+        );
+
+        // Not a token program
+        let other = AccountsSnapshot {
+            accounts: vec![(key, Some(token_account(Pubkey::new_unique(), 4)))],
+        };
+        assert_eq!(
+            before.diff(&other, &[]).accounts[0].token_amount_delta(),
+            None
+        );
+        assert_eq!(
+            SplTokenDecoder.decode(other.accounts[0].1.as_ref().unwrap()),
+            None
+        );
+    }
+
+    #[solana_program_test::tokio::test(crate = "solana_program_test::tokio")]
+    async fn test_sign_send_instructions_with_diff() {
+        let mut program_test = ProgramTest::default();
+        let env = TestEnv::builder()
+            .mint(6)
+            .users(2)
+            .user_balance(0, 0, 1_000)
+            .build(&mut program_test);
+        let mut context = program_test.start_with_context().await;
+        let (sender, receiver, mint) = (env.user(0), env.user(1), env.mint(0));
+        let (sender_ata, receiver_ata) = (sender.ata(&mint.key), receiver.ata(&mint.key));
+        let watched = [
+            sender.pubkey(),
+            receiver.pubkey(),
+            sender_ata,
+            receiver_ata,
+            mint.key,
+        ];
+        let before = context.snapshot_accounts(&watched).await.unwrap();
+
+        let instructions = [
+            spl_token::instruction::transfer(
+                &spl_token::ID,
+                &sender_ata,
+                &receiver_ata,
+                &sender.pubkey(),
+                &[],
+                400,
+            )
+            .unwrap(),
+            system_instruction::transfer(&sender.pubkey(), &receiver.pubkey(), 5_000),
+        ];
+        let diff = context
+            .sign_send_instructions_with_diff(&instructions, &[&sender.keypair], &watched, &[])
+            .await
+            .unwrap();
+        let after = context.snapshot_accounts(&watched).await.unwrap();
+
+        let account_diff = |key: Pubkey,
+                            lamports: Option<(u64, u64)>,
+                            data: Vec<DataChange>,
+                            fields: Vec<FieldChange>| AccountDiff {
+            key,
+            before: before.get(&key).cloned(),
+            after: after.get(&key).cloned(),
+            lamports,
+            owner: None,
+            data,
+            fields,
+        };
+        let amount = |before: &str, after: &str| FieldChange {
+            name: "amount".to_owned(),
+            before: Some(before.to_owned()),
+            after: Some(after.to_owned()),
+        };
+        let lamports = before.get(&sender.pubkey()).unwrap().lamports;
+        // The amount is at offset 64 of token accounts: 1000 -> 600 and 0 -> 400
+        assert_eq!(
+            diff,
+            SnapshotDiff {
+                accounts: vec![
+                    account_diff(
+                        sender.pubkey(),
+                        Some((lamports, lamports - 5_000)),
+                        vec![],
+                        vec![]
+                    ),
+                    account_diff(
+                        receiver.pubkey(),
+                        Some((lamports, lamports + 5_000)),
+                        vec![],
+                        vec![]
+                    ),
+                    account_diff(
+                        sender_ata,
+                        None,
+                        vec![change(64, &[0xe8, 0x03], &[0x58, 0x02])],
+                        vec![amount("1000", "600")]
+                    ),
+                    account_diff(
+                        receiver_ata,
+                        None,
+                        vec![change(64, &[0x00, 0x00], &[0x90, 0x01])],
+                        vec![amount("0", "400")]
+                    ),
+                ]
+            }
+        );
+        assert!(diff.is_unchanged(&mint.key));
+        assert_eq!(diff.token_amount_delta(&sender_ata), -400);
+        assert_eq!(diff.token_amount_delta(&receiver_ata), 400);
+        assert_eq!(diff.lamports_delta(&receiver.pubkey()), 5_000);
+    }
+}
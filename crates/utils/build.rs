@@ -0,0 +1,165 @@
+//! Generates `SupportedToken` from `tokens.toml` for the cluster selected by the crate features.
+use serde::Deserialize;
+use solana_pubkey::Pubkey;
+use std::{collections::HashMap, env, fmt::Write, fs, path::Path, str::FromStr};
+
+/// Must match `bonfida_utils::pyth::DEFAULT_PYTH_PUSH`
+const DEFAULT_PYTH_PUSH: &str = "pyt2F414BA6dPttK6RddPZUdHfapoBN24GL5wbrPCou";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Token {
+    variant: String,
+    symbol: String,
+    mint: String,
+    decimals: u8,
+    feed_id: String,
+    shard: u16,
+    price_feed_account: String,
+}
+
+fn parse_pubkey(token: &Token, field: &str, value: &str) -> Pubkey {
+    Pubkey::from_str(value)
+        .unwrap_or_else(|e| panic!("Invalid {} for {}: {}", field, token.variant, e))
+}
+
+fn parse_feed_id(token: &Token) -> [u8; 32] {
+    let s = &token.feed_id;
+    if s.len() != 64 || !s.bytes().all(|c| c.is_ascii_hexdigit()) {
+        panic!("The feed id of {} must be 64 hex characters", token.variant);
+    }
+    let mut feed_id = [0; 32];
+    for (i, byte) in feed_id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+    }
+    feed_id
+}
+
+/// Features are additive, so when both are enabled (e.g. through feature unification) localnet
+/// takes precedence over devnet, which takes precedence over mainnet
+fn cluster() -> &'static str {
+    if env::var_os("CARGO_FEATURE_LOCALNET").is_some() {
+        "localnet"
+    } else if env::var_os("CARGO_FEATURE_DEVNET").is_some() {
+        "devnet"
+    } else {
+        "mainnet"
+    }
+}
+
+fn generate(tokens: &[Token]) -> String {
+    let push_oracle = Pubkey::from_str(DEFAULT_PYTH_PUSH).unwrap();
+    let mut out = String::new();
+    let mut consts = String::new();
+    let (mut all, mut mint, mut from_mint, mut symbol, mut decimals) = (
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+        String::new(),
+    );
+    let (mut shard, mut account, mut feed) = (String::new(), String::new(), String::new());
+
+    for t in tokens {
+        let v = &t.variant;
+        let const_name = format!("{}_MINT", v.to_uppercase());
+        parse_pubkey(t, "mint", &t.mint);
+        let feed_id = parse_feed_id(t);
+        let price_feed_account = parse_pubkey(t, "price_feed_account", &t.price_feed_account);
+        let (expected, _) =
+            Pubkey::find_program_address(&[&t.shard.to_le_bytes(), &feed_id], &push_oracle);
+        if price_feed_account != expected {
+            panic!(
+                "The price feed account of {} should be {}, the account of its feed on shard {}",
+                v, expected, t.shard
+            );
+        }
+
+        writeln!(
+            consts,
+            "const {}: Pubkey = pubkey!(\"{}\");",
+            const_name, t.mint
+        )
+        .unwrap();
+        writeln!(all, "SupportedToken::{},", v).unwrap();
+        writeln!(mint, "SupportedToken::{} => {},", v, const_name).unwrap();
+        writeln!(from_mint, "{} => SupportedToken::{},", const_name, v).unwrap();
+        writeln!(symbol, "SupportedToken::{} => {:?},", v, t.symbol).unwrap();
+        writeln!(decimals, "SupportedToken::{} => {},", v, t.decimals).unwrap();
+        writeln!(shard, "SupportedToken::{} => {},", v, t.shard).unwrap();
+        writeln!(
+            account,
+            "SupportedToken::{} => pubkey!(\"{}\"),",
+            v, t.price_feed_account
+        )
+        .unwrap();
+        writeln!(feed, "SupportedToken::{} => {:?},", v, feed_id).unwrap();
+    }
+
+    let variants = tokens
+        .iter()
+        .map(|t| format!("{},", t.variant))
+        .collect::<String>();
+    write!(
+        out,
+        r#"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SupportedToken {{ {variants} }}
+
+{consts}
+
+impl SupportedToken {{
+    /// Every supported token of the cluster
+    pub const ALL: [SupportedToken; {len}] = [{all}];
+
+    pub const fn mint(self) -> Pubkey {{ match self {{ {mint} }} }}
+
+    pub const fn from_mint(mint: &Pubkey) -> Result<Self, ProgramError> {{
+        Ok(match *mint {{
+            {from_mint}
+            _ => return Err(ProgramError::InvalidArgument),
+        }})
+    }}
+
+    pub const fn symbol(self) -> &'static str {{ match self {{ {symbol} }} }}
+
+    pub const fn decimals(self) -> u8 {{ match self {{ {decimals} }} }}
+
+    /// Shard of the Pyth push oracle price feed account
+    pub const fn price_feed_shard(self) -> u16 {{ match self {{ {shard} }} }}
+
+    pub const fn price_feed_account_key(self) -> Pubkey {{ match self {{ {account} }} }}
+
+    pub const fn price_feed(self) -> [u8; 32] {{ match self {{ {feed} }} }}
+}}
+"#,
+        len = tokens.len(),
+    )
+    .unwrap();
+    out
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=tokens.toml");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let source = fs::read_to_string("tokens.toml").expect("Failed to read tokens.toml");
+    let mut clusters: HashMap<String, Vec<Token>> =
+        toml::from_str(&source).unwrap_or_else(|e| panic!("Invalid tokens.toml: {}", e));
+    let cluster = cluster();
+    let tokens = clusters
+        .remove(cluster)
+        .unwrap_or_else(|| panic!("tokens.toml has no {} tokens", cluster));
+
+    for (i, t) in tokens.iter().enumerate() {
+        for other in &tokens[..i] {
+            if t.variant == other.variant || t.symbol == other.symbol || t.mint == other.mint {
+                panic!("{} and {} are duplicates", other.variant, t.variant);
+            }
+        }
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("tokens.rs"), generate(&tokens))
+        .expect("Failed to write the generated tokens");
+}
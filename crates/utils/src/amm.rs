@@ -0,0 +1,343 @@
+//! Constant product (x * y = k) AMM math.
+//!
+//! All amounts are raw token amounts. Every function rounds in favour of the pool: amounts paid
+//! out by the pool are rounded down, amounts owed to the pool and fees are rounded up. As a
+//! result the pool invariant can never decrease. `None` is returned on overflow or when the
+//! reserves cannot support the operation.
+use std::convert::TryInto;
+
+use crate::fp_math::{isqrt, mul_div_ceil, mul_div_floor};
+
+/// Fees are expressed in basis points of this denominator
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Selects which side of a swap the fee is taken from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeMode {
+    /// The fee is taken from the input amount before it goes through the curve
+    BeforeCurve,
+    /// The fee is taken from the output amount after it goes through the curve
+    AfterCurve,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapQuote {
+    /// Amount paid by the user, including the fee when it is taken before the curve
+    pub amount_in: u64,
+    /// Amount received by the user, net of the fee when it is taken after the curve
+    pub amount_out: u64,
+    /// Fee amount, denominated in the input token for [`FeeMode::BeforeCurve`] and in the
+    /// output token for [`FeeMode::AfterCurve`]
+    pub fee: u64,
+}
+
+/// Returns the fee owed on an amount, rounded up
+pub fn fee_amount(amount: u64, fee_bps: u16) -> Option<u64> {
+    if fee_bps as u64 > BPS_DENOMINATOR {
+        return None;
+    }
+    mul_div_ceil(amount as u128, fee_bps as u128, BPS_DENOMINATOR as u128)
+        .and_then(|x| x.try_into().ok())
+}
+
+/// Returns the output of the curve for an exact input, rounded down
+pub fn curve_amount_out(amount_in: u64, reserve_in: u64, reserve_out: u64) -> Option<u64> {
+    let new_reserve_in = (reserve_in as u128).checked_add(amount_in as u128)?;
+    mul_div_floor(reserve_out as u128, amount_in as u128, new_reserve_in)
+        .and_then(|x| x.try_into().ok())
+}
+
+/// Returns the input the curve requires for an exact output, rounded up
+pub fn curve_amount_in(amount_out: u64, reserve_in: u64, reserve_out: u64) -> Option<u64> {
+    if amount_out >= reserve_out {
+        return None;
+    }
+    mul_div_ceil(
+        reserve_in as u128,
+        amount_out as u128,
+        (reserve_out - amount_out) as u128,
+    )
+    .and_then(|x| x.try_into().ok())
+}
+
+/// Quotes a swap of an exact input amount. The output is rounded down and the fee rounded up.
+pub fn quote_out(
+    amount_in: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+    fee_mode: FeeMode,
+) -> Option<SwapQuote> {
+    match fee_mode {
+        FeeMode::BeforeCurve => {
+            let fee = fee_amount(amount_in, fee_bps)?;
+            let amount_out = curve_amount_out(amount_in - fee, reserve_in, reserve_out)?;
+            Some(SwapQuote {
+                amount_in,
+                amount_out,
+                fee,
+            })
+        }
+        FeeMode::AfterCurve => {
+            let gross_out = curve_amount_out(amount_in, reserve_in, reserve_out)?;
+            let fee = fee_amount(gross_out, fee_bps)?;
+            Some(SwapQuote {
+                amount_in,
+                amount_out: gross_out - fee,
+                fee,
+            })
+        }
+    }
+}
+
+/// Quotes a swap for an exact output amount. The required input is rounded up.
+///
+/// Feeding the returned `amount_in` to [`quote_out`] yields at least `amount_out`.
+pub fn quote_in(
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+    fee_bps: u16,
+    fee_mode: FeeMode,
+) -> Option<SwapQuote> {
+    match fee_mode {
+        FeeMode::BeforeCurve => {
+            let net_in = curve_amount_in(amount_out, reserve_in, reserve_out)?;
+            let amount_in = gross_up(net_in, fee_bps)?;
+            Some(SwapQuote {
+                amount_in,
+                amount_out,
+                fee: fee_amount(amount_in, fee_bps)?,
+            })
+        }
+        FeeMode::AfterCurve => {
+            let gross_out = gross_up(amount_out, fee_bps)?;
+            let amount_in = curve_amount_in(gross_out, reserve_in, reserve_out)?;
+            Some(SwapQuote {
+                amount_in,
+                amount_out,
+                fee: fee_amount(gross_out, fee_bps)?,
+            })
+        }
+    }
+}
+
+/// Returns the smallest amount which is still at least `net` once the fee is deducted
+fn gross_up(net: u64, fee_bps: u16) -> Option<u64> {
+    let fee_bps = fee_bps as u64;
+    if fee_bps >= BPS_DENOMINATOR {
+        return None;
+    }
+    mul_div_ceil(
+        net as u128,
+        BPS_DENOMINATOR as u128,
+        (BPS_DENOMINATOR - fee_bps) as u128,
+    )
+    .and_then(|x| x.try_into().ok())
+}
+
+/// Returns the amount of LP tokens to mint for a deposit, rounded down.
+///
+/// The first deposit mints sqrt(amount_a * amount_b). Later deposits mint in proportion to the
+/// scarcest side, any excess of the other side is donated to the pool.
+pub fn lp_tokens_for_deposit(
+    amount_a: u64,
+    amount_b: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Option<u64> {
+    if lp_supply == 0 {
+        return isqrt(amount_a as u128 * amount_b as u128).try_into().ok();
+    }
+    let from_a = mul_div_floor(amount_a as u128, lp_supply as u128, reserve_a as u128)?;
+    let from_b = mul_div_floor(amount_b as u128, lp_supply as u128, reserve_b as u128)?;
+    from_a.min(from_b).try_into().ok()
+}
+
+/// Returns the token amounts required to mint an exact amount of LP tokens, rounded up
+pub fn deposit_for_lp_tokens(
+    lp_amount: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Option<(u64, u64)> {
+    let amount_a = mul_div_ceil(lp_amount as u128, reserve_a as u128, lp_supply as u128)?;
+    let amount_b = mul_div_ceil(lp_amount as u128, reserve_b as u128, lp_supply as u128)?;
+    Some((amount_a.try_into().ok()?, amount_b.try_into().ok()?))
+}
+
+/// Returns the token amounts paid out when burning LP tokens, rounded down
+pub fn withdraw_for_lp_tokens(
+    lp_amount: u64,
+    reserve_a: u64,
+    reserve_b: u64,
+    lp_supply: u64,
+) -> Option<(u64, u64)> {
+    if lp_amount > lp_supply {
+        return None;
+    }
+    let amount_a = mul_div_floor(lp_amount as u128, reserve_a as u128, lp_supply as u128)?;
+    let amount_b = mul_div_floor(lp_amount as u128, reserve_b as u128, lp_supply as u128)?;
+    Some((amount_a.try_into().ok()?, amount_b.try_into().ok()?))
+}
+
+/// Returns the spot price of the input token in output tokens in fp32, rounded down
+pub fn spot_price_fp32(reserve_in: u64, reserve_out: u64) -> Option<u64> {
+    mul_div_floor(reserve_out as u128, 1 << 32, reserve_in as u128).and_then(|x| x.try_into().ok())
+}
+
+/// Returns the price impact of a trade as a fp32 fraction of the spot price, rounded up.
+///
+/// This is 1 - (amount_out / amount_in) / (reserve_out / reserve_in), or zero when the trade
+/// executes at or above the spot price.
+pub fn price_impact_fp32(
+    amount_in: u64,
+    amount_out: u64,
+    reserve_in: u64,
+    reserve_out: u64,
+) -> Option<u64> {
+    let spot_value = amount_in as u128 * reserve_out as u128;
+    let execution_value = amount_out as u128 * reserve_in as u128;
+    if execution_value >= spot_value {
+        return Some(0);
+    }
+    mul_div_ceil(spot_value - execution_value, 1 << 32, spot_value).and_then(|x| x.try_into().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn invariant(reserve_a: u64, reserve_b: u64) -> u128 {
+        reserve_a as u128 * reserve_b as u128
+    }
+
+    fn fee_mode() -> impl Strategy<Value = FeeMode> {
+        prop_oneof![Just(FeeMode::BeforeCurve), Just(FeeMode::AfterCurve)]
+    }
+
+    /// Pool reserves after a swap, assuming the fee leaves the pool
+    fn reserves_after(
+        quote: &SwapQuote,
+        reserve_in: u64,
+        reserve_out: u64,
+        fee_mode: FeeMode,
+    ) -> (u64, u64) {
+        match fee_mode {
+            FeeMode::BeforeCurve => (
+                reserve_in + quote.amount_in - quote.fee,
+                reserve_out - quote.amount_out,
+            ),
+            FeeMode::AfterCurve => (
+                reserve_in + quote.amount_in,
+                reserve_out - quote.amount_out - quote.fee,
+            ),
+        }
+    }
+
+    #[test]
+    fn test() {
+        let quote = quote_out(1_000, 1_000_000, 1_000_000, 30, FeeMode::BeforeCurve).unwrap();
+        assert_eq!(quote.fee, 3);
+        assert_eq!(quote.amount_out, 996);
+        let quote = quote_out(1_000, 1_000_000, 1_000_000, 30, FeeMode::AfterCurve).unwrap();
+        assert_eq!(quote.fee, 3);
+        assert_eq!(quote.amount_out, 996);
+        let quote = quote_in(996, 1_000_000, 1_000_000, 30, FeeMode::BeforeCurve).unwrap();
+        assert_eq!(quote.amount_in, 1_000);
+        assert!(quote_in(1_000_000, 1_000_000, 1_000_000, 0, FeeMode::BeforeCurve).is_none());
+        assert!(fee_amount(1, 10_001).is_none());
+
+        assert_eq!(lp_tokens_for_deposit(100, 400, 0, 0, 0), Some(200));
+        assert_eq!(lp_tokens_for_deposit(10, 50, 100, 400, 200), Some(20));
+        assert_eq!(deposit_for_lp_tokens(20, 100, 400, 200), Some((10, 40)));
+        assert_eq!(withdraw_for_lp_tokens(20, 101, 401, 200), Some((10, 40)));
+        assert!(withdraw_for_lp_tokens(201, 100, 400, 200).is_none());
+
+        assert_eq!(spot_price_fp32(100, 400), Some(4 << 32));
+        assert_eq!(price_impact_fp32(100, 400, 100, 400), Some(0));
+        assert_eq!(price_impact_fp32(100, 200, 100, 400), Some(1 << 31));
+    }
+
+    proptest! {
+        #[test]
+        fn quote_out_never_decreases_invariant(
+            amount_in in 0..u64::MAX / 4,
+            reserve_in in 1..u64::MAX / 4,
+            reserve_out in 1..u64::MAX,
+            fee_bps in 0u16..=10_000,
+            fee_mode in fee_mode(),
+        ) {
+            let quote = quote_out(amount_in, reserve_in, reserve_out, fee_bps, fee_mode).unwrap();
+            let (new_in, new_out) = reserves_after(&quote, reserve_in, reserve_out, fee_mode);
+            prop_assert!(invariant(new_in, new_out) >= invariant(reserve_in, reserve_out));
+        }
+
+        #[test]
+        fn quote_in_never_decreases_invariant(
+            amount_out: u64,
+            reserve_in in 1..u64::MAX / 4,
+            reserve_out in 1..u64::MAX,
+            fee_bps in 0u16..10_000,
+            fee_mode in fee_mode(),
+        ) {
+            let amount_out = amount_out % reserve_out;
+            let quote = match quote_in(amount_out, reserve_in, reserve_out, fee_bps, fee_mode) {
+                Some(q) => q,
+                // The required input does not fit in a u64
+                None => return Ok(()),
+            };
+            prop_assume!(quote.amount_in < u64::MAX - reserve_in);
+            let (new_in, new_out) = reserves_after(&quote, reserve_in, reserve_out, fee_mode);
+            prop_assert!(invariant(new_in, new_out) >= invariant(reserve_in, reserve_out));
+
+            // The quoted input always buys at least the requested output
+            let out = quote_out(quote.amount_in, reserve_in, reserve_out, fee_bps, fee_mode).unwrap();
+            prop_assert!(out.amount_out >= amount_out);
+        }
+
+        #[test]
+        fn deposit_never_dilutes_shares(
+            amount_a in 0..u32::MAX as u64,
+            amount_b in 0..u32::MAX as u64,
+            reserve_a in 1..u32::MAX as u64,
+            reserve_b in 1..u32::MAX as u64,
+            lp_supply in 1..u32::MAX as u64,
+        ) {
+            let minted = lp_tokens_for_deposit(amount_a, amount_b, reserve_a, reserve_b, lp_supply).unwrap();
+            let new_supply = (lp_supply + minted) as u128;
+            // Reserves per LP token never decrease
+            prop_assert!((reserve_a + amount_a) as u128 * lp_supply as u128 >= reserve_a as u128 * new_supply);
+            prop_assert!((reserve_b + amount_b) as u128 * lp_supply as u128 >= reserve_b as u128 * new_supply);
+        }
+
+        #[test]
+        fn withdraw_never_dilutes_shares(
+            lp_amount: u64,
+            reserve_a: u64,
+            reserve_b: u64,
+            lp_supply in 1..u64::MAX,
+        ) {
+            let lp_amount = lp_amount % (lp_supply + 1);
+            let (out_a, out_b) = withdraw_for_lp_tokens(lp_amount, reserve_a, reserve_b, lp_supply).unwrap();
+            let new_supply = (lp_supply - lp_amount) as u128;
+            // Reserves per LP token never decrease
+            prop_assert!((reserve_a - out_a) as u128 * lp_supply as u128 >= reserve_a as u128 * new_supply);
+            prop_assert!((reserve_b - out_b) as u128 * lp_supply as u128 >= reserve_b as u128 * new_supply);
+        }
+
+        #[test]
+        fn deposit_for_lp_tokens_covers_mint(
+            lp_amount in 1..u32::MAX as u64,
+            reserve_a in 1..u32::MAX as u64,
+            reserve_b in 1..u32::MAX as u64,
+            lp_supply in 1..u32::MAX as u64,
+        ) {
+            let (a, b) = deposit_for_lp_tokens(lp_amount, reserve_a, reserve_b, lp_supply).unwrap();
+            let minted = lp_tokens_for_deposit(a, b, reserve_a, reserve_b, lp_supply).unwrap();
+            prop_assert!(minted >= lp_amount);
+        }
+    }
+}
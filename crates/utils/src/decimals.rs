@@ -0,0 +1,278 @@
+//! Conversions between token amounts of different decimals, UI amounts and oracle prices.
+use std::convert::TryInto;
+
+use crate::fp_math::mul_div_floor;
+
+/// Rounding direction for conversions which can lose precision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ParseAmountError {
+    #[error("The amount is empty")]
+    Empty,
+    #[error("The amount contains an invalid character")]
+    InvalidCharacter,
+    #[error("The amount has more fractional digits than the token's decimals")]
+    TooManyDecimals,
+    #[error("The amount does not fit in a u64")]
+    Overflow,
+}
+
+/// Returns 10^exponent, or `None` if it does not fit in a u128
+pub const fn pow10(exponent: u32) -> Option<u128> {
+    10u128.checked_pow(exponent)
+}
+
+/// Converts an amount expressed with `from_decimals` into `to_decimals`
+pub fn rescale(amount: u64, from_decimals: u8, to_decimals: u8, rounding: Rounding) -> Option<u64> {
+    rescale_u128(amount as u128, from_decimals, to_decimals, rounding)
+        .and_then(|x| x.try_into().ok())
+}
+
+/// Converts an amount expressed with `from_decimals` into `to_decimals`
+pub fn rescale_u128(
+    amount: u128,
+    from_decimals: u8,
+    to_decimals: u8,
+    rounding: Rounding,
+) -> Option<u128> {
+    if to_decimals >= from_decimals {
+        return amount.checked_mul(pow10((to_decimals - from_decimals) as u32)?);
+    }
+    Some(div_pow10(
+        amount,
+        (from_decimals - to_decimals) as u32,
+        rounding,
+    ))
+}
+
+/// Returns `amount / 10^exponent`
+fn div_pow10(amount: u128, exponent: u32, rounding: Rounding) -> u128 {
+    let divisor = match pow10(exponent) {
+        Some(d) => d,
+        // The divisor exceeds any u128 amount
+        None => return (rounding == Rounding::Ceil && amount != 0) as u128,
+    };
+    let quotient = amount / divisor;
+    if rounding == Rounding::Ceil && quotient * divisor != amount {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+/// Returns the value of `amount` base tokens in quote tokens, rounded down.
+///
+/// `price_fp32` is the UI price, i.e. the number of whole quote tokens one whole base token is
+/// worth.
+pub fn amount_in_quote(
+    amount: u64,
+    price_fp32: u64,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Option<u64> {
+    let value_fp32 = amount as u128 * price_fp32 as u128;
+    let value = if quote_decimals >= base_decimals {
+        let factor = pow10((quote_decimals - base_decimals) as u32)?;
+        mul_div_floor(value_fp32, factor, 1 << 32)?
+    } else {
+        rescale_u128(
+            value_fp32 >> 32,
+            base_decimals,
+            quote_decimals,
+            Rounding::Floor,
+        )?
+    };
+    value.try_into().ok()
+}
+
+/// Converts an oracle price `price * 10^expo` (in quote per base, UI amounts) into the fp32
+/// price of one raw base token in raw quote tokens, rounded down.
+///
+/// Returns `None` for negative prices or on overflow.
+pub fn oracle_price_to_fp32(
+    price: i64,
+    expo: i32,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Option<u64> {
+    if price < 0 {
+        return None;
+    }
+    scale_oracle_price(
+        (price as u128) << 32,
+        expo,
+        base_decimals,
+        quote_decimals,
+        Rounding::Floor,
+    )?
+    .try_into()
+    .ok()
+}
+
+/// Multiplies `value` by `10^expo` and converts it from UI amounts of quote per base into raw
+/// quote tokens per raw base token.
+///
+/// This is the decimal correction of [`oracle_price_to_fp32`] for prices which are already
+/// fixed point.
+pub fn scale_oracle_price(
+    value: u128,
+    expo: i32,
+    base_decimals: u8,
+    quote_decimals: u8,
+    rounding: Rounding,
+) -> Option<u128> {
+    let exponent = expo
+        .checked_add(quote_decimals as i32)?
+        .checked_sub(base_decimals as i32)?;
+    if exponent >= 0 {
+        value.checked_mul(pow10(exponent as u32)?)
+    } else {
+        Some(div_pow10(value, exponent.unsigned_abs(), rounding))
+    }
+}
+
+/// Parses a decimal string such as `"12.345"` into a raw amount with `decimals` decimals.
+///
+/// Parsing is exact: the string is rejected if it has more fractional digits than `decimals`.
+pub fn parse_ui_amount(s: &str, decimals: u8) -> Result<u64, ParseAmountError> {
+    let (integer, fraction) = match s.split_once('.') {
+        Some((i, f)) => (i, f),
+        None => (s, ""),
+    };
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(ParseAmountError::Empty);
+    }
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > decimals as usize {
+        return Err(ParseAmountError::TooManyDecimals);
+    }
+    let mut amount: u64 = 0;
+    let padding = decimals as usize - fraction.len();
+    for c in integer
+        .chars()
+        .chain(fraction.chars())
+        .chain(std::iter::repeat_n('0', padding))
+    {
+        let digit = c.to_digit(10).ok_or(ParseAmountError::InvalidCharacter)?;
+        amount = amount
+            .checked_mul(10)
+            .and_then(|x| x.checked_add(digit as u64))
+            .ok_or(ParseAmountError::Overflow)?;
+    }
+    Ok(amount)
+}
+
+/// Formats a raw amount with `decimals` decimals as an exact decimal string without trailing
+/// zeros, e.g. `1_500_000` with 6 decimals gives `"1.5"`
+pub fn format_ui_amount(amount: u64, decimals: u8) -> String {
+    let digits = amount.to_string();
+    let decimals = decimals as usize;
+    let (integer, fraction) = if digits.len() > decimals {
+        let (i, f) = digits.split_at(digits.len() - decimals);
+        (i.to_owned(), f.to_owned())
+    } else {
+        (
+            "0".to_owned(),
+            format!("{:0>width$}", digits, width = decimals),
+        )
+    };
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        integer
+    } else {
+        format!("{}.{}", integer, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            rescale(1_234_567, 6, 9, Rounding::Floor),
+            Some(1_234_567_000)
+        );
+        assert_eq!(rescale(1_234_567, 9, 6, Rounding::Floor), Some(1_234));
+        assert_eq!(rescale(1_234_567, 9, 6, Rounding::Ceil), Some(1_235));
+        assert_eq!(rescale(1_234_000, 9, 6, Rounding::Ceil), Some(1_234));
+        assert_eq!(rescale(u64::MAX, 0, 1, Rounding::Floor), None);
+        assert_eq!(rescale(1, 255, 0, Rounding::Ceil), Some(1));
+        assert_eq!(rescale(1, 255, 0, Rounding::Floor), Some(0));
+
+        // 1.5 SOL at 20 USDC
+        assert_eq!(
+            amount_in_quote(1_500_000_000, 20 << 32, 9, 6),
+            Some(30_000_000)
+        );
+        assert_eq!(
+            amount_in_quote(30_000_000, 1 << 27, 6, 9),
+            Some(937_500_000)
+        );
+
+        // SOL/USD at 148.45 with an exponent of -8, in raw USDC per raw SOL
+        let price = oracle_price_to_fp32(14_845_000_000, -8, 9, 6).unwrap();
+        assert_eq!(price, (148_450u128 << 32) as u64 / 1_000_000);
+        assert_eq!(oracle_price_to_fp32(-1, -8, 9, 6), None);
+        assert_eq!(oracle_price_to_fp32(1, 30, 0, 0), None);
+        assert_eq!(scale_oracle_price(15, -1, 0, 0, Rounding::Floor), Some(1));
+        assert_eq!(scale_oracle_price(15, -1, 0, 0, Rounding::Ceil), Some(2));
+        assert_eq!(scale_oracle_price(15, -300, 0, 0, Rounding::Ceil), Some(1));
+
+        assert_eq!(parse_ui_amount("1.5", 6), Ok(1_500_000));
+        assert_eq!(parse_ui_amount(".5", 6), Ok(500_000));
+        assert_eq!(parse_ui_amount("2.", 2), Ok(200));
+        assert_eq!(parse_ui_amount("0.1234560", 6), Ok(123_456));
+        assert_eq!(
+            parse_ui_amount("0.1234567", 6),
+            Err(ParseAmountError::TooManyDecimals)
+        );
+        assert_eq!(
+            parse_ui_amount("1,5", 6),
+            Err(ParseAmountError::InvalidCharacter)
+        );
+        assert_eq!(
+            parse_ui_amount("-1", 6),
+            Err(ParseAmountError::InvalidCharacter)
+        );
+        assert_eq!(parse_ui_amount(".", 6), Err(ParseAmountError::Empty));
+        assert_eq!(
+            parse_ui_amount("18446744073709551616", 0),
+            Err(ParseAmountError::Overflow)
+        );
+
+        assert_eq!(format_ui_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_ui_amount(500, 6), "0.0005");
+        assert_eq!(format_ui_amount(2_000_000, 6), "2");
+        assert_eq!(format_ui_amount(0, 6), "0");
+        assert_eq!(format_ui_amount(42, 0), "42");
+    }
+
+    proptest! {
+        #[test]
+        fn format_parse_round_trip(amount: u64, decimals in 0u8..20) {
+            prop_assert_eq!(parse_ui_amount(&format_ui_amount(amount, decimals), decimals), Ok(amount));
+        }
+
+        #[test]
+        fn rescale_round_trip(amount in 0..u32::MAX as u64, from in 0u8..10, extra in 0u8..10) {
+            let up = rescale(amount, from, from + extra, Rounding::Floor).unwrap();
+            prop_assert_eq!(rescale(up, from + extra, from, Rounding::Floor), Some(amount));
+            prop_assert_eq!(rescale(up, from + extra, from, Rounding::Ceil), Some(amount));
+        }
+
+        #[test]
+        fn rescale_rounding(amount: u64, from in 0u8..40, to in 0u8..19) {
+            let floor = rescale_u128(amount as u128, from, to, Rounding::Floor).unwrap();
+            let ceil = rescale_u128(amount as u128, from, to, Rounding::Ceil).unwrap();
+            prop_assert!(ceil - floor <= 1);
+        }
+    }
+}
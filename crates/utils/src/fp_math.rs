@@ -1,14 +1,47 @@
+//! Fixed point helpers.
+//!
+//! All functions take an integer (fp0) operand and a fixed point operand (fp32 or fp64) and
+//! return an integer (fp0). The `_floor` variants round toward -∞ and the `_ceil` variants round
+//! toward +∞, for both signed and unsigned operands. `None` is returned on overflow or when
+//! dividing by zero.
+//!
+//! The `_u128` variants and [`mul_div_floor`] / [`mul_div_ceil`] keep a 256-bit intermediate so
+//! that they can be used with u128 amounts. Square root, power, logarithm and exponential are
+//! provided for fp32 and fp64 values.
 use std::convert::TryInto;
 
+mod mul_div;
+mod transcendental;
+
+pub use mul_div::{
+    fp64_div_ceil_u128, fp64_div_floor_u128, fp64_mul_ceil_u128, fp64_mul_floor_u128, mul_div_ceil,
+    mul_div_floor,
+};
+pub(crate) use transcendental::isqrt;
+pub use transcendental::{
+    fp32_exp, fp32_ln, fp32_pow, fp32_pow_frac, fp32_sqrt, fp64_exp, fp64_ln, fp64_pow,
+    fp64_pow_frac, fp64_sqrt,
+};
+
 pub const FP_32_ONE: u64 = 1 << 32;
 
 /// a is fp0, b is fp32 and result is a/b fp0
 pub fn fp32_div(a: u64, b_fp32: u64) -> Option<u64> {
+    fp32_div_floor(a, b_fp32)
+}
+
+/// a is fp0, b is fp32 and result is a/b fp0 rounded down
+pub fn fp32_div_floor(a: u64, b_fp32: u64) -> Option<u64> {
     ((a as u128) << 32)
         .checked_div(b_fp32 as u128)
         .and_then(|x| x.try_into().ok())
 }
 
+/// a is fp0, b is fp32 and result is a/b fp0 rounded up
+pub fn fp32_div_ceil(a: u64, b_fp32: u64) -> Option<u64> {
+    div_ceil_util((a as u128) << 32, b_fp32 as u128).and_then(|x| x.try_into().ok())
+}
+
 /// a is fp0, b is fp32 and result is a*b fp0
 pub fn fp32_mul_floor(a: u64, b_fp32: u64) -> Option<u64> {
     (a as u128)
@@ -26,41 +59,73 @@ pub fn fp32_mul_ceil(a: u64, b_fp32: u64) -> Option<u64> {
 
 /// a is fp0, b is fp32 and result is a/b fp0
 pub fn ifp32_div(a: i64, b_fp32: u64) -> Option<i64> {
-    ((a.unsigned_abs() as u128) << 32)
-        .checked_div(b_fp32 as u128)
-        .and_then(|x| x.try_into().ok())
-        .map(|x: i64| a.signum() * x)
+    ifp32_div_floor(a, b_fp32)
 }
 
-/// a is fp0, b is fp32 and result is a*b fp0
+/// a is fp0, b is fp32 and result is a/b fp0 rounded toward -∞
+pub fn ifp32_div_floor(a: i64, b_fp32: u64) -> Option<i64> {
+    let numerator = (a.unsigned_abs() as u128) << 32;
+    if a < 0 {
+        div_ceil_util(numerator, b_fp32 as u128).and_then(neg_util)
+    } else {
+        numerator
+            .checked_div(b_fp32 as u128)
+            .and_then(|x| x.try_into().ok())
+    }
+}
+
+/// a is fp0, b is fp32 and result is a/b fp0 rounded toward +∞
+pub fn ifp32_div_ceil(a: i64, b_fp32: u64) -> Option<i64> {
+    let numerator = (a.unsigned_abs() as u128) << 32;
+    if a < 0 {
+        numerator.checked_div(b_fp32 as u128).and_then(neg_util)
+    } else {
+        div_ceil_util(numerator, b_fp32 as u128).and_then(|x| x.try_into().ok())
+    }
+}
+
+/// a is fp0, b is fp32 and result is a*b fp0 rounded toward -∞
 pub fn ifp32_mul_floor(a: i64, b_fp32: u64) -> Option<i64> {
-    (a.unsigned_abs() as u128)
-        .checked_mul(b_fp32 as u128)
-        .and_then(|x| (x >> 32).try_into().ok())
-        .map(|x: i64| a.signum() * x)
+    let product = (a.unsigned_abs() as u128).checked_mul(b_fp32 as u128)?;
+    if a < 0 {
+        fp32_ceil_util(product).and_then(|x| neg_util(x >> 32))
+    } else {
+        (product >> 32).try_into().ok()
+    }
 }
 
-/// a is fp0, b is fp32 and result is a*b fp0
+/// a is fp0, b is fp32 and result is a*b fp0 rounded toward +∞
 pub fn ifp32_mul_ceil(a: i64, b_fp32: u64) -> Option<i64> {
-    (a.unsigned_abs() as u128)
-        .checked_mul(b_fp32 as u128)
-        .and_then(fp32_ceil_util)
-        .and_then(|x| (x >> 32).try_into().ok())
-        .map(|x: i64| a.signum() * x)
+    let product = (a.unsigned_abs() as u128).checked_mul(b_fp32 as u128)?;
+    if a < 0 {
+        neg_util(product >> 32)
+    } else {
+        fp32_ceil_util(product).and_then(|x| (x >> 32).try_into().ok())
+    }
 }
 
 /// a is fp0, b is fp64 and result is a/b fp0
 pub fn fp64_div(a: u64, b_fp64: u64) -> Option<u64> {
+    fp64_div_floor(a, b_fp64)
+}
+
+/// a is fp0, b is fp64 and result is a/b fp0 rounded down
+pub fn fp64_div_floor(a: u64, b_fp64: u64) -> Option<u64> {
     ((a as u128) << 64)
         .checked_div(b_fp64 as u128)
         .and_then(|x| x.try_into().ok())
 }
 
+/// a is fp0, b is fp64 and result is a/b fp0 rounded up
+pub fn fp64_div_ceil(a: u64, b_fp64: u64) -> Option<u64> {
+    div_ceil_util((a as u128) << 64, b_fp64 as u128).and_then(|x| x.try_into().ok())
+}
+
 /// a is fp0, b is fp64 and result is a*b fp0
 pub fn fp64_mul_floor(a: u64, b_fp64: u64) -> Option<u64> {
     (a as u128)
         .checked_mul(b_fp64 as u128)
-        .map(|x| (x >> 64))
+        .map(|x| x >> 64)
         .and_then(|x| x.try_into().ok())
 }
 
@@ -68,11 +133,53 @@ pub fn fp64_mul_floor(a: u64, b_fp64: u64) -> Option<u64> {
 pub fn fp64_mul_ceil(a: u64, b_fp64: u64) -> Option<u64> {
     (a as u128)
         .checked_mul(b_fp64 as u128)
-        .map(|x| (x >> 64))
         .and_then(fp64_ceil_util)
+        .map(|x| x >> 64)
         .and_then(|x| x.try_into().ok())
 }
 
+/// a is fp0, b is fp64 and result is a/b fp0 rounded toward -∞
+pub fn ifp64_div_floor(a: i64, b_fp64: u64) -> Option<i64> {
+    let numerator = (a.unsigned_abs() as u128) << 64;
+    if a < 0 {
+        div_ceil_util(numerator, b_fp64 as u128).and_then(neg_util)
+    } else {
+        numerator
+            .checked_div(b_fp64 as u128)
+            .and_then(|x| x.try_into().ok())
+    }
+}
+
+/// a is fp0, b is fp64 and result is a/b fp0 rounded toward +∞
+pub fn ifp64_div_ceil(a: i64, b_fp64: u64) -> Option<i64> {
+    let numerator = (a.unsigned_abs() as u128) << 64;
+    if a < 0 {
+        numerator.checked_div(b_fp64 as u128).and_then(neg_util)
+    } else {
+        div_ceil_util(numerator, b_fp64 as u128).and_then(|x| x.try_into().ok())
+    }
+}
+
+/// a is fp0, b is fp64 and result is a*b fp0 rounded toward -∞
+pub fn ifp64_mul_floor(a: i64, b_fp64: u64) -> Option<i64> {
+    let product = (a.unsigned_abs() as u128).checked_mul(b_fp64 as u128)?;
+    if a < 0 {
+        fp64_ceil_util(product).and_then(|x| neg_util(x >> 64))
+    } else {
+        (product >> 64).try_into().ok()
+    }
+}
+
+/// a is fp0, b is fp64 and result is a*b fp0 rounded toward +∞
+pub fn ifp64_mul_ceil(a: i64, b_fp64: u64) -> Option<i64> {
+    let product = (a.unsigned_abs() as u128).checked_mul(b_fp64 as u128)?;
+    if a < 0 {
+        neg_util(product >> 64)
+    } else {
+        fp64_ceil_util(product).and_then(|x| (x >> 64).try_into().ok())
+    }
+}
+
 #[inline(always)]
 fn fp32_ceil_util(x_fp32: u128) -> Option<u128> {
     let add_one = (!(x_fp32 as u32)).wrapping_add(1) as u128;
@@ -85,11 +192,35 @@ fn fp64_ceil_util(x_fp64: u128) -> Option<u128> {
     x_fp64.checked_add(add_one)
 }
 
+#[inline(always)]
+fn div_ceil_util(a: u128, b: u128) -> Option<u128> {
+    let quotient = a.checked_div(b)?;
+    if quotient * b < a {
+        Some(quotient + 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Negates a magnitude, returning `None` when it does not fit in an `i64`
+#[inline(always)]
+fn neg_util(x: u128) -> Option<i64> {
+    if x > i64::MIN.unsigned_abs() as u128 {
+        return None;
+    }
+    Some((x as i64).wrapping_neg())
+}
+
 #[test]
 fn test() {
     // fp32_div
     assert_eq!(fp32_div(124345678765454, 45654 << 32).unwrap(), 2723653541);
     assert_eq!(fp32_div(124345678765454, 6787654 << 32).unwrap(), 18319389);
+    assert_eq!(
+        fp32_div_ceil(124345678765454, 6787654 << 32).unwrap(),
+        18319390
+    );
+    assert_eq!(fp32_div_ceil(10, 5 << 32).unwrap(), 2);
 
     // fp32_mul
     assert_eq!(
@@ -108,6 +239,9 @@ fn test() {
     // ifp32_div
     assert_eq!(ifp32_div(124345678765454, 6787654 << 32).unwrap(), 18319389);
     assert_eq!(ifp32_div(124345678765454, 45654 << 32).unwrap(), 2723653541);
+    assert_eq!(ifp32_div_floor(-5, 2 << 32).unwrap(), -3);
+    assert_eq!(ifp32_div_ceil(-5, 2 << 32).unwrap(), -2);
+    assert_eq!(ifp32_div_ceil(5, 2 << 32).unwrap(), 3);
 
     // ifp32_mul
     assert_eq!(
@@ -115,12 +249,115 @@ fn test() {
         38530409800122
     );
     assert_eq!(ifp32_mul_floor(12454, 45654 << 32).unwrap(), 568574916);
+    assert_eq!(ifp32_mul_floor(-5, 1 << 31).unwrap(), -3);
+    assert_eq!(ifp32_mul_ceil(-5, 1 << 31).unwrap(), -2);
+    assert_eq!(ifp32_mul_ceil(5, 1 << 31).unwrap(), 3);
+    assert_eq!(ifp32_mul_floor(i64::MIN, 1 << 32).unwrap(), i64::MIN);
 
     // fp64_div
     assert_eq!(fp64_div(5676543, 345678909876543456).unwrap(), 302921968);
     assert_eq!(fp64_div(12454, 345678909876543456).unwrap(), 664592);
+    assert_eq!(fp64_div_ceil(12454, 345678909876543456).unwrap(), 664593);
 
     // fp64_mul
     assert_eq!(fp64_mul_floor(5676543, 345678909876543456).unwrap(), 106374);
-    assert_eq!(fp64_mul_floor(12454, 345678909876543456).unwrap(), 233)
+    assert_eq!(fp64_mul_floor(12454, 345678909876543456).unwrap(), 233);
+    assert_eq!(fp64_mul_ceil(5676543, 345678909876543456).unwrap(), 106375);
+    assert_eq!(fp64_mul_ceil(5, 1 << 63).unwrap(), 3);
+
+    // ifp64_mul
+    assert_eq!(ifp64_mul_floor(-5, 1 << 63).unwrap(), -3);
+    assert_eq!(ifp64_mul_ceil(-5, 1 << 63).unwrap(), -2);
+    assert_eq!(ifp64_div_floor(-1, 3 << 62).unwrap(), -2);
+    assert_eq!(ifp64_div_ceil(-1, 3 << 62).unwrap(), -1);
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use num_bigint::BigInt;
+    use num_integer::Integer;
+    use proptest::prelude::*;
+    use std::convert::TryFrom;
+
+    fn reference_mul(a: impl Into<BigInt>, b: u64, shift: usize, ceil: bool) -> BigInt {
+        let numerator = a.into() * BigInt::from(b);
+        let denominator = BigInt::from(1u8) << shift;
+        reference_div_util(numerator, denominator, ceil)
+    }
+
+    fn reference_div(a: impl Into<BigInt>, b: u64, shift: usize, ceil: bool) -> BigInt {
+        let numerator = a.into() << shift;
+        reference_div_util(numerator, BigInt::from(b), ceil)
+    }
+
+    fn reference_div_util(numerator: BigInt, denominator: BigInt, ceil: bool) -> BigInt {
+        if ceil {
+            -((-numerator).div_floor(&denominator))
+        } else {
+            numerator.div_floor(&denominator)
+        }
+    }
+
+    fn check_u64(result: Option<u64>, reference: BigInt) {
+        assert_eq!(result, u64::try_from(&reference).ok());
+    }
+
+    fn check_i64(result: Option<i64>, reference: BigInt) {
+        assert_eq!(result, i64::try_from(&reference).ok());
+    }
+
+    proptest! {
+        #[test]
+        fn unsigned_mul(a: u64, b: u64) {
+            check_u64(fp32_mul_floor(a, b), reference_mul(a, b, 32, false));
+            check_u64(fp32_mul_ceil(a, b), reference_mul(a, b, 32, true));
+            check_u64(fp64_mul_floor(a, b), reference_mul(a, b, 64, false));
+            check_u64(fp64_mul_ceil(a, b), reference_mul(a, b, 64, true));
+        }
+
+        #[test]
+        fn unsigned_div(a: u64, b in 1..u64::MAX) {
+            check_u64(fp32_div_floor(a, b), reference_div(a, b, 32, false));
+            check_u64(fp32_div_ceil(a, b), reference_div(a, b, 32, true));
+            check_u64(fp64_div_floor(a, b), reference_div(a, b, 64, false));
+            check_u64(fp64_div_ceil(a, b), reference_div(a, b, 64, true));
+        }
+
+        #[test]
+        fn signed_mul(a: i64, b: u64) {
+            check_i64(ifp32_mul_floor(a, b), reference_mul(a, b, 32, false));
+            check_i64(ifp32_mul_ceil(a, b), reference_mul(a, b, 32, true));
+            check_i64(ifp64_mul_floor(a, b), reference_mul(a, b, 64, false));
+            check_i64(ifp64_mul_ceil(a, b), reference_mul(a, b, 64, true));
+        }
+
+        #[test]
+        fn signed_div(a: i64, b in 1..u64::MAX) {
+            check_i64(ifp32_div_floor(a, b), reference_div(a, b, 32, false));
+            check_i64(ifp32_div_ceil(a, b), reference_div(a, b, 32, true));
+            check_i64(ifp64_div_floor(a, b), reference_div(a, b, 64, false));
+            check_i64(ifp64_div_ceil(a, b), reference_div(a, b, 64, true));
+        }
+
+        #[test]
+        fn small_operands(a in -(1i64 << 20)..(1i64 << 20), b in 1u64..(1 << 40)) {
+            check_i64(ifp32_mul_floor(a, b), reference_mul(a, b, 32, false));
+            check_i64(ifp32_mul_ceil(a, b), reference_mul(a, b, 32, true));
+            check_i64(ifp32_div_floor(a, b), reference_div(a, b, 32, false));
+            check_i64(ifp32_div_ceil(a, b), reference_div(a, b, 32, true));
+        }
+
+        #[test]
+        fn division_by_zero(a: u64, c: i64) {
+            prop_assert!(fp32_div_floor(a, 0).is_none());
+            prop_assert!(fp32_div_ceil(a, 0).is_none());
+            prop_assert!(fp64_div_floor(a, 0).is_none());
+            prop_assert!(fp64_div_ceil(a, 0).is_none());
+            prop_assert!(ifp32_div_floor(c, 0).is_none());
+            prop_assert!(ifp32_div_ceil(c, 0).is_none());
+            prop_assert!(ifp64_div_floor(c, 0).is_none());
+            prop_assert!(ifp64_div_ceil(c, 0).is_none());
+        }
+    }
 }
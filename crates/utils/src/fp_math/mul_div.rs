@@ -0,0 +1,200 @@
+//! `a * b / c` over u128 operands with a 256-bit intermediate product.
+//!
+//! Only `core` integer operations are used so that these primitives run on-chain.
+
+const LOW_MASK: u128 = u64::MAX as u128;
+const HALF: u128 = 1 << 64;
+
+/// a, b and c are fp0 and result is a*b/c fp0 rounded down
+pub fn mul_div_floor(a: u128, b: u128, c: u128) -> Option<u128> {
+    let (hi, lo) = full_mul(a, b);
+    div_256_by_128(hi, lo, c).map(|(quotient, _)| quotient)
+}
+
+/// a, b and c are fp0 and result is a*b/c fp0 rounded up
+pub fn mul_div_ceil(a: u128, b: u128, c: u128) -> Option<u128> {
+    let (hi, lo) = full_mul(a, b);
+    let (quotient, remainder) = div_256_by_128(hi, lo, c)?;
+    if remainder != 0 {
+        quotient.checked_add(1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// a is fp0, b is fp64 and result is a*b fp0 rounded down
+pub fn fp64_mul_floor_u128(a: u128, b_fp64: u128) -> Option<u128> {
+    let (hi, lo) = full_mul(a, b_fp64);
+    shr_64(hi, lo)
+}
+
+/// a is fp0, b is fp64 and result is a*b fp0 rounded up
+pub fn fp64_mul_ceil_u128(a: u128, b_fp64: u128) -> Option<u128> {
+    let (hi, lo) = full_mul(a, b_fp64);
+    let result = shr_64(hi, lo)?;
+    if lo & LOW_MASK != 0 {
+        result.checked_add(1)
+    } else {
+        Some(result)
+    }
+}
+
+/// a is fp0, b is fp64 and result is a/b fp0 rounded down
+pub fn fp64_div_floor_u128(a: u128, b_fp64: u128) -> Option<u128> {
+    mul_div_floor(a, HALF, b_fp64)
+}
+
+/// a is fp0, b is fp64 and result is a/b fp0 rounded up
+pub fn fp64_div_ceil_u128(a: u128, b_fp64: u128) -> Option<u128> {
+    mul_div_ceil(a, HALF, b_fp64)
+}
+
+/// Returns the 256-bit product of a and b as (high, low) words
+#[inline(always)]
+fn full_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a1, a0) = (a >> 64, a & LOW_MASK);
+    let (b1, b0) = (b >> 64, b & LOW_MASK);
+    let p00 = a0 * b0;
+    let p01 = a0 * b1;
+    let p10 = a1 * b0;
+    let p11 = a1 * b1;
+    // At most 3 * (2^64 - 1), no overflow
+    let mid = (p00 >> 64) + (p01 & LOW_MASK) + (p10 & LOW_MASK);
+    let lo = (p00 & LOW_MASK) | (mid << 64);
+    let hi = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+#[inline(always)]
+fn shr_64(hi: u128, lo: u128) -> Option<u128> {
+    if hi >> 64 != 0 {
+        return None;
+    }
+    Some((hi << 64) | (lo >> 64))
+}
+
+/// Divides the 256-bit (hi, lo) by d, returning (quotient, remainder).
+///
+/// This is Knuth's algorithm D specialized to two 64-bit digits (Hacker's Delight `divlu`).
+/// Returns `None` when d is zero or when the quotient does not fit in a u128.
+fn div_256_by_128(hi: u128, lo: u128, d: u128) -> Option<(u128, u128)> {
+    if d == 0 || hi >= d {
+        return None;
+    }
+    if hi == 0 {
+        return Some((lo / d, lo % d));
+    }
+
+    // Normalize the divisor so that its most significant bit is set
+    let s = d.leading_zeros();
+    let v = d << s;
+    let (vn1, vn0) = (v >> 64, v & LOW_MASK);
+    let un32 = if s == 0 {
+        hi
+    } else {
+        (hi << s) | (lo >> (128 - s))
+    };
+    let un10 = lo << s;
+    let (un1, un0) = (un10 >> 64, un10 & LOW_MASK);
+
+    let q1 = estimate_digit(un32, un1, vn1, vn0);
+    let un21 = un32
+        .wrapping_mul(HALF)
+        .wrapping_add(un1)
+        .wrapping_sub(q1.wrapping_mul(v));
+
+    let q0 = estimate_digit(un21, un0, vn1, vn0);
+    let remainder = un21
+        .wrapping_mul(HALF)
+        .wrapping_add(un0)
+        .wrapping_sub(q0.wrapping_mul(v));
+
+    Some(((q1 << 64) | q0, remainder >> s))
+}
+
+#[inline(always)]
+fn estimate_digit(numerator: u128, next_digit: u128, vn1: u128, vn0: u128) -> u128 {
+    let mut q = numerator / vn1;
+    let mut rhat = numerator - q * vn1;
+    while q >= HALF || q * vn0 > (rhat << 64) + next_digit {
+        q -= 1;
+        rhat += vn1;
+        if rhat >= HALF {
+            break;
+        }
+    }
+    q
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+    use num_integer::Integer;
+    use proptest::prelude::*;
+    use std::convert::TryFrom;
+
+    fn reference(a: u128, b: u128, c: u128, ceil: bool) -> Option<u128> {
+        let product = BigUint::from(a) * BigUint::from(b);
+        let (quotient, remainder) = product.div_rem(&BigUint::from(c));
+        let quotient = if ceil && remainder != BigUint::from(0u8) {
+            quotient + 1u8
+        } else {
+            quotient
+        };
+        u128::try_from(&quotient).ok()
+    }
+
+    #[test]
+    fn test() {
+        assert_eq!(
+            mul_div_floor(u128::MAX, u128::MAX, u128::MAX),
+            Some(u128::MAX)
+        );
+        assert_eq!(mul_div_floor(u128::MAX, 2, 3), Some(u128::MAX / 3 * 2));
+        assert_eq!(mul_div_ceil(10, 10, 3), Some(34));
+        assert_eq!(mul_div_floor(1, 1, 0), None);
+        assert_eq!(mul_div_floor(u128::MAX, 2, 1), None);
+        assert_eq!(mul_div_ceil(u128::MAX, u128::MAX, u128::MAX - 1), None);
+
+        assert_eq!(fp64_mul_floor_u128(u128::MAX, 1 << 63), Some(u128::MAX / 2));
+        assert_eq!(
+            fp64_mul_ceil_u128(u128::MAX, 1 << 63),
+            Some(u128::MAX / 2 + 1)
+        );
+        assert_eq!(fp64_mul_floor_u128(u128::MAX, 3 << 63), None);
+        assert_eq!(fp64_div_floor_u128(u128::MAX, 2 << 64), Some(u128::MAX / 2));
+        assert_eq!(
+            fp64_div_ceil_u128(u128::MAX, 2 << 64),
+            Some(u128::MAX / 2 + 1)
+        );
+        assert_eq!(fp64_div_floor_u128(1 << 64, 1 << 63), Some(1 << 65));
+    }
+
+    proptest! {
+        #[test]
+        fn mul_div(a: u128, b: u128, c: u128) {
+            prop_assume!(c != 0);
+            prop_assert_eq!(mul_div_floor(a, b, c), reference(a, b, c, false));
+            prop_assert_eq!(mul_div_ceil(a, b, c), reference(a, b, c, true));
+        }
+
+        #[test]
+        fn mul_div_close_operands(a: u128, b in 1..u64::MAX as u128, d in 0..u64::MAX as u128) {
+            // Quotients near u128::MAX stress the digit estimation loop
+            let c = a.saturating_sub(d).max(1);
+            prop_assert_eq!(mul_div_floor(a, b, c), reference(a, b, c, false));
+            prop_assert_eq!(mul_div_ceil(a, b, c), reference(a, b, c, true));
+        }
+
+        #[test]
+        fn fp64(a: u128, b: u128) {
+            prop_assert_eq!(fp64_mul_floor_u128(a, b), reference(a, b, HALF, false));
+            prop_assert_eq!(fp64_mul_ceil_u128(a, b), reference(a, b, HALF, true));
+            if b != 0 {
+                prop_assert_eq!(fp64_div_floor_u128(a, b), reference(a, HALF, b, false));
+                prop_assert_eq!(fp64_div_ceil_u128(a, b), reference(a, HALF, b, true));
+            }
+        }
+    }
+}
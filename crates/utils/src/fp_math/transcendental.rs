@@ -0,0 +1,353 @@
+//! Fixed point square root, power, logarithm and exponential.
+//!
+//! Everything is computed with integer arithmetic only, so results are identical on every target.
+//! fp64 values are stored in a `u128` (or `i128` when signed) so that they can exceed one. The
+//! fp32 variants are computed in fp64 and then rounded down.
+//!
+//! Every function rounds down: the returned value is never greater than the exact result.
+use std::convert::TryInto;
+
+use super::mul_div::{fp64_mul_floor_u128, mul_div_ceil, mul_div_floor};
+
+const ONE: u128 = 1 << 64;
+/// ln(2) in fp64, rounded down
+const LN2_FLOOR: u128 = 12786308645202655659;
+/// ln(2) in fp64, rounded up
+const LN2_CEIL: u128 = LN2_FLOOR + 1;
+
+/// x is fp32 and result is sqrt(x) fp32, rounded down.
+///
+/// The result is exact: it is the largest fp32 value whose square does not exceed x.
+pub fn fp32_sqrt(x_fp32: u64) -> u64 {
+    // sqrt(x * 2^32) < 2^64
+    isqrt((x_fp32 as u128) << 32) as u64
+}
+
+/// x is fp64 and result is sqrt(x) fp64, rounded down.
+///
+/// The result is exact: it is the largest fp64 value whose square does not exceed x.
+pub fn fp64_sqrt(x_fp64: u128) -> u128 {
+    if x_fp64 == 0 {
+        return 0;
+    }
+    // Newton's method on x * 2^64, starting from an overestimate of the root
+    let mut r = (isqrt(x_fp64) + 1) << 32;
+    loop {
+        // r > 0 so the division is always defined, and x * 2^64 / r < 2^128 for r >= 2^32
+        let y = (r + mul_div_floor(x_fp64, ONE, r).unwrap()) >> 1;
+        if y >= r {
+            return r;
+        }
+        r = y;
+    }
+}
+
+/// x is fp32, n is fp0 and result is x^n fp32, rounded down.
+///
+/// The error is below one ulp plus the error of [`fp64_pow`].
+pub fn fp32_pow(x_fp32: u64, n: u32) -> Option<u64> {
+    fp64_pow((x_fp32 as u128) << 32, n).and_then(fp64_to_fp32)
+}
+
+/// x is fp64, n is fp0 and result is x^n fp64, rounded down.
+///
+/// Computed by repeated squaring. When x >= 1 the relative error is below n * 2^-62, when
+/// x < 1 the absolute error is below n * 2^-63.
+pub fn fp64_pow(x_fp64: u128, mut n: u32) -> Option<u128> {
+    let mut result = ONE;
+    let mut base = x_fp64;
+    while n != 0 {
+        if n & 1 == 1 {
+            result = fp64_mul_floor_u128(result, base)?;
+        }
+        n >>= 1;
+        if n != 0 {
+            base = fp64_mul_floor_u128(base, base)?;
+        }
+    }
+    Some(result)
+}
+
+/// x and y are fp32 and result is x^y fp32, rounded down.
+///
+/// Computed as exp(y * ln(x)), see [`fp64_pow_frac`] for error bounds.
+pub fn fp32_pow_frac(x_fp32: u64, y_fp32: u64) -> Option<u64> {
+    fp64_pow_frac((x_fp32 as u128) << 32, (y_fp32 as u128) << 32).and_then(fp64_to_fp32)
+}
+
+/// x and y are fp64 and result is x^y fp64, rounded down.
+///
+/// Computed as exp(y * ln(x)). The relative error is below (1 + y) * 2^-54, plus one ulp.
+pub fn fp64_pow_frac(x_fp64: u128, y_fp64: u128) -> Option<u128> {
+    if y_fp64 == 0 {
+        return Some(ONE);
+    }
+    if x_fp64 == 0 {
+        return Some(0);
+    }
+    let ln_x = fp64_ln(x_fp64)?;
+    // Rounding y * ln(x) toward -∞ keeps the final result below the exact value
+    let exponent = if ln_x >= 0 {
+        mul_div_floor(ln_x as u128, y_fp64, ONE)?.try_into().ok()?
+    } else {
+        match mul_div_ceil(ln_x.unsigned_abs(), y_fp64, ONE) {
+            Some(m) if m <= i128::MAX as u128 => -(m as i128),
+            // The result underflows to zero
+            _ => return Some(0),
+        }
+    };
+    fp64_exp(exponent)
+}
+
+/// x is fp32 and result is ln(x) fp32, rounded down.
+///
+/// Returns `None` when x is zero. The absolute error is below 2^-31.
+pub fn fp32_ln(x_fp32: u64) -> Option<i64> {
+    fp64_ln((x_fp32 as u128) << 32).map(|x| (x >> 32) as i64)
+}
+
+/// x is fp64 and result is ln(x) fp64, rounded down.
+///
+/// Returns `None` when x is zero. The absolute error is below 2^-56.
+pub fn fp64_ln(x_fp64: u128) -> Option<i128> {
+    if x_fp64 == 0 {
+        return None;
+    }
+    // x = 2^k * m with m in [1, 2)
+    let k = 63 - x_fp64.leading_zeros() as i32;
+    let m = if k >= 0 { x_fp64 >> k } else { x_fp64 << -k };
+
+    // ln(m) = 2 * atanh(z) = 2 * (z + z^3/3 + z^5/5 + ...) with z = (m - 1) / (m + 1) < 1/3
+    let z = mul_div_floor(m - ONE, ONE, m + ONE)?;
+    let z_squared = (z * z) >> 64;
+    let mut power = z;
+    let mut sum = 0;
+    let mut i = 1;
+    while power != 0 {
+        sum += power / i;
+        power = (power * z_squared) >> 64;
+        i += 2;
+    }
+    let ln_m = (sum << 1) as i128;
+
+    let ln_2k = if k >= 0 {
+        (k as u128 * LN2_FLOOR) as i128
+    } else {
+        -(((-k) as u128 * LN2_CEIL) as i128)
+    };
+    Some(ln_2k + ln_m)
+}
+
+/// x is fp32 and result is exp(x) fp32, rounded down.
+///
+/// Returns `None` on overflow. The error is below one ulp plus a relative error of 2^-56.
+pub fn fp32_exp(x_fp32: i64) -> Option<u64> {
+    fp64_exp((x_fp32 as i128) << 32).and_then(fp64_to_fp32)
+}
+
+/// x is fp64 and result is exp(x) fp64, rounded down.
+///
+/// Returns `None` on overflow. The relative error is below 2^-56, plus one ulp.
+pub fn fp64_exp(x_fp64: i128) -> Option<u128> {
+    // x = k * ln(2) + r with r in [0, ln(2)). The rounding of ln(2) is chosen so that r is never
+    // overestimated.
+    let (k, r) = if x_fp64 >= 0 {
+        let x = x_fp64 as u128;
+        let k = x / LN2_CEIL;
+        if k >= 64 {
+            return None;
+        }
+        (k as i32, x - k * LN2_CEIL)
+    } else {
+        let x = x_fp64.unsigned_abs();
+        let k = x.div_ceil(LN2_FLOOR);
+        if k > 65 {
+            return Some(0);
+        }
+        (-(k as i32), k * LN2_FLOOR - x)
+    };
+
+    // exp(r) = 1 + r + r^2/2! + ...
+    let mut sum = ONE;
+    let mut term = ONE;
+    let mut i = 1;
+    loop {
+        term = (term * r) / (ONE * i);
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        i += 1;
+    }
+
+    if k >= 0 {
+        // exp(r) < 2 so sum < 2^65
+        if sum.leading_zeros() < k as u32 {
+            return None;
+        }
+        Some(sum << k)
+    } else {
+        Some(sum >> -k)
+    }
+}
+
+fn fp64_to_fp32(x_fp64: u128) -> Option<u64> {
+    (x_fp64 >> 32).try_into().ok()
+}
+
+/// Integer square root, rounded down
+pub(crate) fn isqrt(x: u128) -> u128 {
+    if x < 2 {
+        return x;
+    }
+    // 2^ceil(bits / 2) is an overestimate of the root
+    let bits = 128 - x.leading_zeros();
+    let mut r = 1u128 << bits.div_ceil(2);
+    loop {
+        let y = (r + x / r) >> 1;
+        if y >= r {
+            return r;
+        }
+        r = y;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use num_bigint::BigUint;
+    use proptest::prelude::*;
+
+    const ONE_F64: f64 = ONE as f64;
+
+    fn to_f64(x_fp64: u128) -> f64 {
+        x_fp64 as f64 / ONE_F64
+    }
+
+    fn to_f64_signed(x_fp64: i128) -> f64 {
+        x_fp64 as f64 / ONE_F64
+    }
+
+    /// Checks that `result` is within `rel` relative error plus `abs` of `reference`
+    fn assert_close(result: f64, reference: f64, rel: f64, abs: f64) {
+        // f64 references carry their own rounding error
+        let tolerance = reference.abs() * (rel + f64::EPSILON * 4.0) + abs;
+        assert!(
+            (result - reference).abs() <= tolerance,
+            "result {} reference {} tolerance {}",
+            result,
+            reference,
+            tolerance
+        );
+    }
+
+    #[test]
+    fn test() {
+        assert_eq!(fp32_sqrt(4 << 32), 2 << 32);
+        assert_eq!(fp32_sqrt(1 << 30), 1 << 31);
+        assert_eq!(fp64_sqrt(9 << 64), 3 << 64);
+        assert_eq!(fp64_sqrt(u128::MAX), (1 << 96) - 1);
+        assert_eq!(fp32_pow(3 << 32, 4), Some(81 << 32));
+        assert_eq!(fp64_pow(1 << 63, 3), Some(1 << 61));
+        assert_eq!(fp32_pow(1 << 48, 2), None);
+        assert_eq!(fp64_pow(0, 0), Some(ONE));
+        assert_eq!(fp64_ln(ONE), Some(0));
+        assert_eq!(fp64_ln(0), None);
+        assert_eq!(fp64_ln(2 << 64), Some(LN2_FLOOR as i128));
+        assert_eq!(fp64_exp(0), Some(ONE));
+        assert_eq!(fp64_exp(45 << 64), None);
+        assert_eq!(fp64_exp(-50 << 64), Some(0));
+        assert_eq!(fp32_exp(-(23 << 32)), Some(0));
+        let sqrt_4 = fp64_pow_frac(4 << 64, 1 << 63).unwrap();
+        assert!(sqrt_4 <= 2 << 64 && (2 << 64) - sqrt_4 < 1 << 10);
+        assert_eq!(fp64_pow_frac(0, 1 << 63), Some(0));
+        assert_eq!(fp64_pow_frac(0, 0), Some(ONE));
+    }
+
+    proptest! {
+        #[test]
+        fn sqrt_is_exact_floor(x: u128) {
+            let r = BigUint::from(fp64_sqrt(x));
+            let n = BigUint::from(x) << 64;
+            prop_assert!(&r * &r <= n);
+            prop_assert!((&r + 1u8) * (&r + 1u8) > n);
+        }
+
+        #[test]
+        fn fp32_sqrt_is_exact_floor(x: u64) {
+            let r = fp32_sqrt(x) as u128;
+            let n = (x as u128) << 32;
+            prop_assert!(r * r <= n);
+            prop_assert!((r + 1) * (r + 1) > n);
+        }
+
+        #[test]
+        fn pow_bounds(x in 0..(4u128 << 64), n in 0u32..64) {
+            // x^n in fp64 is x^n / 2^(64 * (n - 1))
+            let exact = BigUint::from(x).pow(n) << 64u8;
+            let scale = BigUint::from(1u8) << (64 * n as usize);
+            let exact_floor = &exact / &scale;
+            match fp64_pow(x, n) {
+                None => prop_assert!(exact_floor >= BigUint::from(1u8) << 128u8),
+                Some(r) => {
+                    let r = BigUint::from(r);
+                    prop_assert!(&r * &scale <= exact);
+                    // Absolute error below n * 2^-63, relative error below n * 2^-62
+                    let tolerance = BigUint::from(2 * n + 1).max((&exact_floor * n) >> 62u8);
+                    prop_assert!(&exact_floor - &r <= tolerance);
+                }
+            }
+        }
+
+        #[test]
+        fn ln_matches_reference(x in 1u128..) {
+            let result = fp64_ln(x).unwrap();
+            // Converting x to f64 shifts the reference by up to f64::EPSILON
+            assert_close(to_f64_signed(result), to_f64(x).ln(), 0.0, 2f64.powi(-56) + f64::EPSILON);
+        }
+
+        #[test]
+        fn fp32_ln_matches_reference(x in 1u64..) {
+            let result = fp32_ln(x).unwrap() as f64 / FP_32_ONE_F64;
+            let reference = (x as f64 / FP_32_ONE_F64).ln();
+            prop_assert!(result <= reference + f64::EPSILON * 64.0);
+            assert_close(result, reference, 0.0, 2f64.powi(-32));
+        }
+
+        #[test]
+        fn exp_matches_reference(x in -(50i128 << 64)..(44i128 << 64)) {
+            let result = fp64_exp(x).unwrap();
+            let reference = to_f64_signed(x).exp();
+            let rel = 2f64.powi(-56) + to_f64_signed(x).abs() * f64::EPSILON;
+            assert_close(to_f64(result), reference, rel, 2f64.powi(-64));
+        }
+
+        #[test]
+        fn fp32_exp_matches_reference(x in -(30i64 << 32)..(22i64 << 32)) {
+            let result = fp32_exp(x).unwrap() as f64 / FP_32_ONE_F64;
+            let reference = (x as f64 / FP_32_ONE_F64).exp();
+            assert_close(result, reference, 2f64.powi(-56), 2f64.powi(-32));
+        }
+
+        #[test]
+        fn pow_frac_matches_reference(x in 1u128..(1u128 << 80), y in 0u128..(4u128 << 64)) {
+            let reference = to_f64(x).powf(to_f64(y));
+            prop_assume!(reference < 2f64.powi(63));
+            let result = fp64_pow_frac(x, y).unwrap();
+            let rel = (1.0 + to_f64(y)) * 2f64.powi(-54) + reference.ln().abs() * f64::EPSILON * 2.0;
+            assert_close(to_f64(result), reference, rel, 2f64.powi(-64));
+        }
+
+        #[test]
+        fn exp_is_monotonic(x in -(40i128 << 64)..(40i128 << 64), d in 0i128..(1 << 64)) {
+            prop_assert!(fp64_exp(x).unwrap() <= fp64_exp(x + d).unwrap());
+        }
+
+        #[test]
+        fn ln_is_monotonic(x in 1u128..u128::MAX / 2, d: u64) {
+            prop_assert!(fp64_ln(x).unwrap() <= fp64_ln(x + d as u128).unwrap());
+        }
+    }
+
+    const FP_32_ONE_F64: f64 = (1u64 << 32) as f64;
+}
@@ -0,0 +1,135 @@
+//! Interest accrual from an annual rate, compounded every second.
+//!
+//! Rates are fp64 values stored in a `u128`, e.g. a 5% APR is `(1 << 64) / 20`. Growth factors
+//! and indices are fp64 as well.
+use bytemuck::{Pod, Zeroable};
+use solana_program::program_error::ProgramError;
+
+use crate::{
+    fp_math::{fp64_exp, fp64_mul_ceil_u128, fp64_mul_floor_u128, mul_div_floor},
+    time::{elapsed_seconds, SECONDS_PER_YEAR},
+};
+
+const ONE: u128 = 1 << 64;
+
+/// Returns the growth factor (1 + rate / SECONDS_PER_YEAR)^elapsed_s in fp64, rounded down.
+///
+/// This uses the first four terms of the binomial expansion, which always underestimates the
+/// exact value. With t = rate * elapsed_s / SECONDS_PER_YEAR the relative error is below
+/// t^4 * e^t / 24, about 5e-6 for a 10% APR left unaccrued for a year. Updating more often keeps
+/// the error negligible.
+pub fn accrue(rate_fp64: u128, elapsed_s: u64) -> Option<u128> {
+    let n = elapsed_s as u128;
+    let year = SECONDS_PER_YEAR as u128;
+    // n * x, n(n-1)/2 * x^2 and n(n-1)(n-2)/6 * x^3 with x = rate / SECONDS_PER_YEAR, each term
+    // being derived from the previous one to keep full fp64 precision
+    let first = mul_div_floor(rate_fp64, n, year)?;
+    let second =
+        fp64_mul_floor_u128(first, mul_div_floor(rate_fp64, n.saturating_sub(1), year)?)? / 2;
+    let third =
+        fp64_mul_floor_u128(second, mul_div_floor(rate_fp64, n.saturating_sub(2), year)?)? / 3;
+    ONE.checked_add(first)?
+        .checked_add(second)?
+        .checked_add(third)
+}
+
+/// Returns the continuously compounded growth factor exp(rate * elapsed_s / SECONDS_PER_YEAR)
+/// in fp64, rounded down.
+///
+/// This is the limit of per-second compounding and exceeds it by a relative 2^-25 at most for a
+/// 100% APR. See [`fp64_exp`] for the error bounds.
+pub fn accrue_continuous(rate_fp64: u128, elapsed_s: u64) -> Option<u128> {
+    let exponent = mul_div_floor(rate_fp64, elapsed_s as u128, SECONDS_PER_YEAR as u128)?;
+    if exponent > i128::MAX as u128 {
+        return None;
+    }
+    fp64_exp(exponent as i128)
+}
+
+/// A cumulative interest index which can be stored in a `Pod` account.
+///
+/// [`InterestIndex::apply`] converts an amount recorded when the index was created into its
+/// current value.
+#[derive(Clone, Copy, Zeroable, Pod, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct InterestIndex {
+    /// fp64 cumulative growth since creation
+    pub index_fp64: u128,
+    pub last_update_ts: i64,
+    _padding: [u8; 8],
+}
+
+impl InterestIndex {
+    pub fn new(ts: i64) -> Self {
+        Self {
+            index_fp64: ONE,
+            last_update_ts: ts,
+            _padding: [0; 8],
+        }
+    }
+
+    /// Accrues interest at `rate_fp64` up to `now_ts`, failing if the clock went backwards.
+    ///
+    /// The index is rounded up so that the debt it tracks is never underestimated.
+    pub fn update(&mut self, rate_fp64: u128, now_ts: i64) -> Result<(), ProgramError> {
+        let elapsed = elapsed_seconds(self.last_update_ts, now_ts)?;
+        self.index_fp64 = accrue(rate_fp64, elapsed)
+            .and_then(|growth| fp64_mul_ceil_u128(self.index_fp64, growth))
+            .ok_or(ProgramError::ArithmeticOverflow)?;
+        self.last_update_ts = now_ts;
+        Ok(())
+    }
+
+    /// Returns the value of `amount` accrued since the index was created, rounded down
+    pub fn apply(&self, amount: u64) -> Option<u128> {
+        fp64_mul_floor_u128(amount as u128, self.index_fp64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const ONE_F64: f64 = ONE as f64;
+
+    #[test]
+    fn test() {
+        assert_eq!(accrue(ONE / 10, 0), Some(ONE));
+        assert_eq!(accrue(0, SECONDS_PER_YEAR), Some(ONE));
+        // 10% APR compounded every second for a year is very close to e^0.1
+        let growth = accrue(ONE / 10, SECONDS_PER_YEAR).unwrap() as f64 / ONE_F64;
+        assert!(growth < 0.1f64.exp() && 0.1f64.exp() - growth < 5e-6);
+        let growth = accrue_continuous(ONE / 10, SECONDS_PER_YEAR).unwrap() as f64 / ONE_F64;
+        assert!((growth - 0.1f64.exp()).abs() < 1e-12);
+
+        let mut index = InterestIndex::new(0);
+        index.update(ONE / 10, SECONDS_PER_YEAR as i64).unwrap();
+        assert_eq!(index.apply(1_000_000).unwrap(), 1_105_166);
+        assert!(index.update(ONE / 10, 0).is_err());
+        assert_eq!(std::mem::size_of::<InterestIndex>(), 32);
+    }
+
+    /// (1 + rate / SECONDS_PER_YEAR)^elapsed computed in f64
+    fn reference(rate: u128, elapsed: u64) -> f64 {
+        let x = rate as f64 / ONE_F64 / SECONDS_PER_YEAR as f64;
+        (elapsed as f64 * x.ln_1p()).exp()
+    }
+
+    proptest! {
+        #[test]
+        fn accrue_underestimates_compounding(rate in 0..ONE, elapsed in 0u64..SECONDS_PER_YEAR) {
+            let reference = reference(rate, elapsed);
+            let approx = accrue(rate, elapsed).unwrap() as f64 / ONE_F64;
+            prop_assert!(approx <= reference * (1.0 + 1e-12));
+            let t = rate as f64 / ONE_F64 * elapsed as f64 / SECONDS_PER_YEAR as f64;
+            prop_assert!(reference - approx <= reference * (t.powi(4) * t.exp() / 24.0 + 1e-12));
+        }
+
+        #[test]
+        fn continuous_bounds_compounding(rate in 0..ONE, elapsed in 0u64..SECONDS_PER_YEAR) {
+            let continuous = accrue_continuous(rate, elapsed).unwrap() as f64 / ONE_F64;
+            prop_assert!(continuous * (1.0 + 1e-12) >= reference(rate, elapsed));
+        }
+    }
+}
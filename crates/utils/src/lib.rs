@@ -11,12 +11,20 @@ mod declare_id_with_central_state;
 pub mod tokens;
 mod wrapped_pod;
 
+pub mod amm;
+pub mod decimals;
 pub mod fp_math;
+pub mod interest;
+pub mod lst;
+pub mod oracle;
 pub mod pyth;
+pub mod switchboard;
+pub mod time;
+pub mod vesting;
 pub use accounts::InstructionsAccount;
 pub use bonfida_macros::{
     compute_hashv as compute_record_hash, compute_record_hash_v2, declare_id_with_central_state,
-    BorshSize, InstructionsAccount, WrappedPod, WrappedPodMut,
+    feed_id, BorshSize, InstructionsAccount, WrappedPod, WrappedPodMut,
 };
 pub use borsh_size::BorshSize;
 pub use wrapped_pod::{WrappedPod, WrappedPodMut};
@@ -0,0 +1,400 @@
+//! Exchange rates of liquid staking tokens, read from their stake pool accounts.
+//!
+//! SPL stake pools (e.g. bSOL) and the Marinade state (mSOL) are parsed without their SDKs, only
+//! decoding the fields needed to compute the number of lamports backing one pool token.
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, msg, program_error::ProgramError, pubkey,
+    pubkey::Pubkey,
+};
+use std::convert::{TryFrom, TryInto};
+
+use crate::{
+    fp_math::mul_div_floor,
+    pyth::{utils::GetPriceError, with_pull_oracle},
+    tokens::SupportedToken,
+};
+
+pub const SPL_STAKE_POOL_PROGRAM_ID: Pubkey =
+    pubkey!("SPoo1Ku8WFXoNDMHPsrGSTSG1Y47rzgn41SLUNakuHy");
+pub const MARINADE_PROGRAM_ID: Pubkey = pubkey!("MarBmsSgKXdrN1egZf5sqe1TMai9K1rChYNDJgjq7aD");
+pub const MARINADE_STATE_DISCRIMINATOR: [u8; 8] = [216, 146, 107, 94, 104, 75, 182, 177];
+
+/// Decimals of SOL and of the liquid staking tokens
+const LAMPORTS_DECIMALS: u8 = 9;
+
+// Offsets in the Borsh encoded SPL `StakePool`
+const STAKE_POOL_ACCOUNT_TYPE: u8 = 1;
+const STAKE_POOL_MINT_OFFSET: usize = 162;
+const STAKE_POOL_TOTAL_LAMPORTS_OFFSET: usize = 258;
+const STAKE_POOL_TOKEN_SUPPLY_OFFSET: usize = 266;
+const STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET: usize = 274;
+const STAKE_POOL_MIN_LEN: usize = 282;
+
+// Offsets in the Marinade `State`, discriminator included
+const MARINADE_MSOL_MINT_OFFSET: usize = 8;
+const MARINADE_DELAYED_UNSTAKE_COOLING_DOWN_OFFSET: usize = 226;
+const MARINADE_TOTAL_ACTIVE_BALANCE_OFFSET: usize = 376;
+const MARINADE_AVAILABLE_RESERVE_BALANCE_OFFSET: usize = 496;
+const MARINADE_MSOL_SUPPLY_OFFSET: usize = 504;
+const MARINADE_CIRCULATING_TICKET_BALANCE_OFFSET: usize = 528;
+const MARINADE_EMERGENCY_COOLING_DOWN_OFFSET: usize = 568;
+const MARINADE_STATE_MIN_LEN: usize = 576;
+
+fn read_pubkey(data: &[u8], offset: usize) -> Pubkey {
+    Pubkey::new_from_array(data[offset..offset + 32].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// The fields of an SPL stake pool which determine its exchange rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StakePool {
+    pub pool_mint: Pubkey,
+    pub total_lamports: u64,
+    pub pool_token_supply: u64,
+    /// Epoch at which `total_lamports` was last updated
+    pub last_update_epoch: u64,
+}
+
+impl StakePool {
+    /// Parses the data of a stake pool account, checking its account type and size
+    pub fn parse(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < STAKE_POOL_MIN_LEN || data[0] != STAKE_POOL_ACCOUNT_TYPE {
+            msg!("Invalid stake pool account");
+            return Err(GetPriceError::InvalidPriceAccount.into());
+        }
+        Ok(Self {
+            pool_mint: read_pubkey(data, STAKE_POOL_MINT_OFFSET),
+            total_lamports: read_u64(data, STAKE_POOL_TOTAL_LAMPORTS_OFFSET),
+            pool_token_supply: read_u64(data, STAKE_POOL_TOKEN_SUPPLY_OFFSET),
+            last_update_epoch: read_u64(data, STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET),
+        })
+    }
+}
+
+/// The fields of the Marinade state which determine the mSOL exchange rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarinadeState {
+    pub msol_mint: Pubkey,
+    pub total_active_balance: u64,
+    pub delayed_unstake_cooling_down: u64,
+    pub emergency_cooling_down: u64,
+    pub available_reserve_balance: u64,
+    /// Lamports owed to the holders of delayed unstake tickets
+    pub circulating_ticket_balance: u64,
+    pub msol_supply: u64,
+}
+
+impl MarinadeState {
+    /// Parses the data of the Marinade state account, checking its discriminator and size
+    pub fn parse(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < MARINADE_STATE_MIN_LEN || !data.starts_with(&MARINADE_STATE_DISCRIMINATOR) {
+            msg!("Invalid Marinade state account");
+            return Err(GetPriceError::InvalidPriceAccount.into());
+        }
+        Ok(Self {
+            msol_mint: read_pubkey(data, MARINADE_MSOL_MINT_OFFSET),
+            total_active_balance: read_u64(data, MARINADE_TOTAL_ACTIVE_BALANCE_OFFSET),
+            delayed_unstake_cooling_down: read_u64(
+                data,
+                MARINADE_DELAYED_UNSTAKE_COOLING_DOWN_OFFSET,
+            ),
+            emergency_cooling_down: read_u64(data, MARINADE_EMERGENCY_COOLING_DOWN_OFFSET),
+            available_reserve_balance: read_u64(data, MARINADE_AVAILABLE_RESERVE_BALANCE_OFFSET),
+            circulating_ticket_balance: read_u64(data, MARINADE_CIRCULATING_TICKET_BALANCE_OFFSET),
+            msol_supply: read_u64(data, MARINADE_MSOL_SUPPLY_OFFSET),
+        })
+    }
+
+    /// Returns the lamports backing the circulating mSOL, as computed by Marinade
+    pub fn total_virtual_staked_lamports(&self) -> u64 {
+        self.total_active_balance
+            .saturating_add(self.delayed_unstake_cooling_down)
+            .saturating_add(self.emergency_cooling_down)
+            .saturating_add(self.available_reserve_balance)
+            .saturating_sub(self.circulating_ticket_balance)
+    }
+}
+
+/// A liquid staking pool whose exchange rate can be read on chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LiquidStakingPool {
+    SplStakePool(StakePool),
+    Marinade(MarinadeState),
+}
+
+impl LiquidStakingPool {
+    /// Parses a stake pool or Marinade state account according to its owner
+    pub fn from_account_info(account: &AccountInfo) -> Result<Self, ProgramError> {
+        let data = account.try_borrow_data()?;
+        match *account.owner {
+            SPL_STAKE_POOL_PROGRAM_ID => StakePool::parse(&data).map(Self::SplStakePool),
+            MARINADE_PROGRAM_ID => MarinadeState::parse(&data).map(Self::Marinade),
+            _ => {
+                msg!(
+                    "Liquid staking pools must be owned by {} or {}",
+                    SPL_STAKE_POOL_PROGRAM_ID,
+                    MARINADE_PROGRAM_ID
+                );
+                Err(GetPriceError::InvalidPriceAccountOwner.into())
+            }
+        }
+    }
+
+    pub fn pool_mint(&self) -> Pubkey {
+        match self {
+            Self::SplStakePool(pool) => pool.pool_mint,
+            Self::Marinade(state) => state.msol_mint,
+        }
+    }
+
+    pub fn total_lamports(&self) -> u64 {
+        match self {
+            Self::SplStakePool(pool) => pool.total_lamports,
+            Self::Marinade(state) => state.total_virtual_staked_lamports(),
+        }
+    }
+
+    pub fn pool_token_supply(&self) -> u64 {
+        match self {
+            Self::SplStakePool(pool) => pool.pool_token_supply,
+            Self::Marinade(state) => state.msol_supply,
+        }
+    }
+
+    /// Fails if the exchange rate was not updated during the current epoch. Marinade updates
+    /// its rate continuously so it is never considered stale.
+    pub fn check_up_to_date(&self, clock: &Clock) -> Result<(), GetPriceError> {
+        match self {
+            Self::SplStakePool(pool) if pool.last_update_epoch < clock.epoch => {
+                msg!(
+                    "The stake pool was last updated at epoch {}",
+                    pool.last_update_epoch
+                );
+                Err(GetPriceError::PriceTooOld)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the number of lamports one pool token is worth in fp64, rounded down. Pools
+    /// without tokens have a rate of 1, as when they are created.
+    pub fn lamports_per_token_fp64(&self) -> u128 {
+        let supply = self.pool_token_supply();
+        if supply == 0 {
+            return 1 << 64;
+        }
+        mul_div_floor(self.total_lamports() as u128, 1 << 64, supply as u128).unwrap()
+    }
+
+    /// Returns the number of lamports one pool token is worth in fp32, rounded down
+    pub fn lamports_per_token_fp32(&self) -> Result<u64, GetPriceError> {
+        u64::try_from(self.lamports_per_token_fp64() >> 32)
+            .map_err(|_| GetPriceError::PriceOverflow)
+    }
+}
+
+/// Returns the fp64 price of one raw `lst_mint` token in raw quote tokens from the exchange rate
+/// of `pool_account` and the SOL price of the Pyth pull oracle update `sol_price_account`
+pub fn get_lst_price_fp64(
+    lst_mint: &Pubkey,
+    pool_account: &AccountInfo,
+    sol_price_account: &AccountInfo,
+    quote_decimals: u8,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u128, ProgramError> {
+    let pool = LiquidStakingPool::from_account_info(pool_account)?;
+    if pool.pool_mint() != *lst_mint {
+        msg!("The pool mint is {}", pool.pool_mint());
+        return Err(GetPriceError::InvalidPriceAccount.into());
+    }
+    pool.check_up_to_date(clock)?;
+    let sol_price = with_pull_oracle(&SupportedToken::Sol.price_feed(), sol_price_account, |o| {
+        o.price_no_older_than(clock, maximum_age)
+    })?
+    .to_fp64(LAMPORTS_DECIMALS, quote_decimals)?;
+    let final_price = mul_div_floor(pool.lamports_per_token_fp64(), sol_price, 1 << 64)
+        .ok_or(GetPriceError::PriceOverflow)?;
+
+    msg!("LST FP64 price value: {:?}", final_price);
+
+    Ok(final_price)
+}
+
+/// Same as [`get_lst_price_fp64`] in fp32
+pub fn get_lst_price_fp32(
+    lst_mint: &Pubkey,
+    pool_account: &AccountInfo,
+    sol_price_account: &AccountInfo,
+    quote_decimals: u8,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u64, ProgramError> {
+    let price = get_lst_price_fp64(
+        lst_mint,
+        pool_account,
+        sol_price_account,
+        quote_decimals,
+        clock,
+        maximum_age,
+    )?;
+    Ok(u64::try_from(price >> 32).map_err(|_| GetPriceError::PriceOverflow)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        oracle::PriceOracle,
+        pyth::{parse_price_v2, PYTH_RECEIVER},
+    };
+    use std::{cell::RefCell, rc::Rc};
+
+    fn stake_pool_fixture(mint: &Pubkey, total_lamports: u64, supply: u64) -> Vec<u8> {
+        let mut data = vec![0; 611];
+        data[0] = STAKE_POOL_ACCOUNT_TYPE;
+        data[STAKE_POOL_MINT_OFFSET..STAKE_POOL_MINT_OFFSET + 32].copy_from_slice(mint.as_ref());
+        data[STAKE_POOL_TOTAL_LAMPORTS_OFFSET..STAKE_POOL_TOTAL_LAMPORTS_OFFSET + 8]
+            .copy_from_slice(&total_lamports.to_le_bytes());
+        data[STAKE_POOL_TOKEN_SUPPLY_OFFSET..STAKE_POOL_TOKEN_SUPPLY_OFFSET + 8]
+            .copy_from_slice(&supply.to_le_bytes());
+        data[STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET..STAKE_POOL_LAST_UPDATE_EPOCH_OFFSET + 8]
+            .copy_from_slice(&500u64.to_le_bytes());
+        data
+    }
+
+    fn marinade_fixture(mint: &Pubkey) -> Vec<u8> {
+        let mut data = vec![0; 784];
+        data[..8].copy_from_slice(&MARINADE_STATE_DISCRIMINATOR);
+        data[MARINADE_MSOL_MINT_OFFSET..MARINADE_MSOL_MINT_OFFSET + 32]
+            .copy_from_slice(mint.as_ref());
+        for (offset, value) in [
+            (MARINADE_TOTAL_ACTIVE_BALANCE_OFFSET, 1_000u64),
+            (MARINADE_DELAYED_UNSTAKE_COOLING_DOWN_OFFSET, 100),
+            (MARINADE_EMERGENCY_COOLING_DOWN_OFFSET, 10),
+            (MARINADE_AVAILABLE_RESERVE_BALANCE_OFFSET, 200),
+            (MARINADE_CIRCULATING_TICKET_BALANCE_OFFSET, 60),
+            (MARINADE_MSOL_SUPPLY_OFFSET, 1_000),
+        ] {
+            data[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        let mint = Pubkey::new_unique();
+        let pool = StakePool::parse(&stake_pool_fixture(&mint, 5_000, 4_000)).unwrap();
+        assert_eq!(
+            pool,
+            StakePool {
+                pool_mint: mint,
+                total_lamports: 5_000,
+                pool_token_supply: 4_000,
+                last_update_epoch: 500,
+            }
+        );
+        let pool = LiquidStakingPool::SplStakePool(pool);
+        assert_eq!(pool.lamports_per_token_fp64(), 5 << 62);
+        assert_eq!(pool.lamports_per_token_fp32(), Ok(5 << 30));
+        assert!(StakePool::parse(&[0; 611]).is_err());
+
+        let state = MarinadeState::parse(&marinade_fixture(&mint)).unwrap();
+        assert_eq!(state.msol_mint, mint);
+        assert_eq!(state.total_virtual_staked_lamports(), 1_250);
+        let state = LiquidStakingPool::Marinade(state);
+        assert_eq!(state.lamports_per_token_fp32(), Ok(5 << 30));
+        assert!(MarinadeState::parse(&marinade_fixture(&mint)[..575]).is_err());
+
+        let empty = LiquidStakingPool::SplStakePool(
+            StakePool::parse(&stake_pool_fixture(&mint, 0, 0)).unwrap(),
+        );
+        assert_eq!(empty.lamports_per_token_fp32(), Ok(1 << 32));
+
+        let clock = Clock {
+            epoch: 501,
+            ..Default::default()
+        };
+        assert_eq!(
+            pool.check_up_to_date(&clock),
+            Err(GetPriceError::PriceTooOld)
+        );
+        assert_eq!(state.check_up_to_date(&clock), Ok(()));
+    }
+
+    #[test]
+    fn test_get_lst_price() {
+        let mint = Pubkey::new_unique();
+        let sol_key = Pubkey::new_unique();
+        let pool_key = Pubkey::new_unique();
+        // SOL/USD update of the Pyth tests
+        let mut sol_data = [
+            34, 241, 35, 99, 157, 126, 244, 205, 96, 49, 71, 4, 52, 13, 237, 223, 55, 31, 212, 36,
+            114, 20, 143, 36, 142, 157, 26, 109, 26, 94, 178, 172, 58, 205, 139, 127, 213, 214,
+            178, 67, 1, 239, 13, 139, 111, 218, 44, 235, 164, 29, 161, 93, 64, 149, 209, 218, 57,
+            42, 13, 47, 142, 208, 198, 199, 188, 15, 76, 250, 200, 194, 128, 181, 109, 151, 237,
+            87, 16, 3, 0, 0, 0, 135, 164, 49, 1, 0, 0, 0, 0, 248, 255, 255, 255, 103, 85, 30, 102,
+            0, 0, 0, 0, 103, 85, 30, 102, 0, 0, 0, 0, 208, 47, 218, 39, 3, 0, 0, 0, 14, 62, 204, 0,
+            0, 0, 0, 0, 255, 5, 134, 15, 0, 0, 0, 0, 0,
+        ];
+        let sol_fp64 = parse_price_v2(&sol_data)
+            .unwrap()
+            .price()
+            .unwrap()
+            .to_fp64(9, 6)
+            .unwrap();
+        let mut pool_data = stake_pool_fixture(&mint, 5_000, 4_000);
+        let (mut sol_lamports, mut pool_lamports) = (0, 0);
+        let sol_account = AccountInfo {
+            data: Rc::new(RefCell::new(&mut sol_data[..])),
+            key: &sol_key,
+            lamports: Rc::new(RefCell::new(&mut sol_lamports)),
+            owner: &PYTH_RECEIVER,
+            #[allow(deprecated)]
+            _unused: u64::MAX,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+        let mut pool_account = AccountInfo {
+            data: Rc::new(RefCell::new(&mut pool_data[..])),
+            key: &pool_key,
+            lamports: Rc::new(RefCell::new(&mut pool_lamports)),
+            owner: &SPL_STAKE_POOL_PROGRAM_ID,
+            #[allow(deprecated)]
+            _unused: u64::MAX,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+        let clock = Clock {
+            epoch: 500,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            get_lst_price_fp64(&mint, &pool_account, &sol_account, 6, &clock, 60),
+            Ok(sol_fp64 * 5 / 4)
+        );
+        assert_eq!(
+            get_lst_price_fp32(&mint, &pool_account, &sol_account, 6, &clock, 60),
+            Ok(((sol_fp64 * 5 / 4) >> 32) as u64)
+        );
+        assert_eq!(
+            get_lst_price_fp32(&sol_key, &pool_account, &sol_account, 6, &clock, 60),
+            Err(GetPriceError::InvalidPriceAccount.into())
+        );
+        assert_eq!(
+            get_lst_price_fp32(&mint, &sol_account, &pool_account, 6, &clock, 60),
+            Err(GetPriceError::InvalidPriceAccountOwner.into())
+        );
+        pool_account.owner = &MARINADE_PROGRAM_ID;
+        assert_eq!(
+            get_lst_price_fp32(&mint, &pool_account, &sol_account, 6, &clock, 60),
+            Err(GetPriceError::InvalidPriceAccount.into())
+        );
+    }
+}
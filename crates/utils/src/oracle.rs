@@ -0,0 +1,626 @@
+//! A common interface over the price oracles supported by this crate.
+//!
+//! [`PriceOracle`] is implemented for legacy Pyth price accounts, Pyth pull oracle updates,
+//! Switchboard on-demand pull feeds and [`MockOracle`], a simple account format meant for tests.
+//!
+//! # Mock oracle accounts
+//!
+//! When the `mock-oracle` feature is enabled, every oracle function of [`crate::pyth`] and
+//! [`crate::switchboard`] also accepts mock accounts. A mock account is owned by [`MOCK_ORACLE_OWNER`] and its data is
+//! [`MOCK_ORACLE_DISCRIMINATOR`] followed by the bytes of a [`MockOracle`], [`MockOracle::LEN`]
+//! bytes in total. The price is `price * 10^exponent` quote tokens per base token in UI amounts,
+//! as with Pyth, and it is subject to the same staleness checks. The feature must never be enabled
+//! in a deployed program.
+use bytemuck::{Pod, Zeroable};
+use pyth_sdk_solana::state::{PriceStatus, SolanaPriceAccount};
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, msg, program_error::ProgramError, pubkey,
+    pubkey::Pubkey,
+};
+use std::convert::{TryFrom, TryInto};
+
+use crate::{
+    decimals::{oracle_price_to_fp32, scale_oracle_price, Rounding},
+    pyth::utils::{GetPriceError, PriceUpdateV2, VerificationLevel},
+};
+
+pub mod aggregate;
+
+/// A price as reported by an oracle: `price * 10^exponent` with a confidence interval of
+/// `conf * 10^exponent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    /// Unix timestamp at which the price was published
+    pub publish_time: i64,
+    /// Slot at which the price was published, or posted for pull oracles
+    pub slot: u64,
+}
+
+/// Side of the confidence interval to use when a conservative price is needed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceBound {
+    /// `price - k * conf`, rounded down and floored at zero
+    Lower,
+    /// `price + k * conf`, rounded up
+    Upper,
+}
+
+impl OraclePrice {
+    /// Returns true if the price was published at most `max_age_s` seconds before the clock
+    pub fn is_no_older_than(&self, clock: &Clock, max_age_s: u64) -> bool {
+        let max_age = i64::try_from(max_age_s).unwrap_or(i64::MAX);
+        self.publish_time.saturating_add(max_age) >= clock.unix_timestamp
+    }
+
+    /// Returns the fp32 price of one raw base token in raw quote tokens, rounded down.
+    ///
+    /// See [`oracle_price_to_fp32`].
+    pub fn to_fp32(&self, base_decimals: u8, quote_decimals: u8) -> Result<u64, GetPriceError> {
+        if self.price < 0 {
+            return Err(GetPriceError::NegativePrice);
+        }
+        oracle_price_to_fp32(self.price, self.exponent, base_decimals, quote_decimals)
+            .ok_or(GetPriceError::PriceOverflow)
+    }
+
+    /// Returns the fp64 price of one raw base token in raw quote tokens, rounded down
+    pub fn to_fp64(&self, base_decimals: u8, quote_decimals: u8) -> Result<u128, GetPriceError> {
+        if self.price < 0 {
+            return Err(GetPriceError::NegativePrice);
+        }
+        scale_oracle_price(
+            (self.price as u128) << 64,
+            self.exponent,
+            base_decimals,
+            quote_decimals,
+            Rounding::Floor,
+        )
+        .ok_or(GetPriceError::PriceOverflow)
+    }
+
+    /// Returns `price ± conf_multiplier * conf` in fp32 as a price of one raw base token in raw
+    /// quote tokens, rounded away from the spot price
+    pub fn bound_fp32(
+        &self,
+        bound: PriceBound,
+        conf_multiplier_fp32: u64,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) -> Result<u64, GetPriceError> {
+        let bound = self.bound_scaled(
+            bound,
+            conf_multiplier_fp32,
+            0,
+            base_decimals,
+            quote_decimals,
+        )?;
+        bound.try_into().map_err(|_| GetPriceError::PriceOverflow)
+    }
+
+    /// Returns `price ± conf_multiplier * conf` in fp64 as a price of one raw base token in raw
+    /// quote tokens, rounded away from the spot price
+    pub fn bound_fp64(
+        &self,
+        bound: PriceBound,
+        conf_multiplier_fp32: u64,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) -> Result<u128, GetPriceError> {
+        self.bound_scaled(
+            bound,
+            conf_multiplier_fp32,
+            32,
+            base_decimals,
+            quote_decimals,
+        )
+    }
+
+    /// Computes the bound in fp(32 + extra_shift)
+    fn bound_scaled(
+        &self,
+        bound: PriceBound,
+        conf_multiplier_fp32: u64,
+        extra_shift: u32,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) -> Result<u128, GetPriceError> {
+        if self.price < 0 {
+            return Err(GetPriceError::NegativePrice);
+        }
+        let price = (self.price as u128) << (32 + extra_shift);
+        let spread = (self.conf as u128 * conf_multiplier_fp32 as u128)
+            .checked_mul(1 << extra_shift)
+            .ok_or(GetPriceError::PriceOverflow)?;
+        let (value, rounding) = match bound {
+            PriceBound::Lower => (price.saturating_sub(spread), Rounding::Floor),
+            PriceBound::Upper => (
+                price
+                    .checked_add(spread)
+                    .ok_or(GetPriceError::PriceOverflow)?,
+                Rounding::Ceil,
+            ),
+        };
+        scale_oracle_price(
+            value,
+            self.exponent,
+            base_decimals,
+            quote_decimals,
+            rounding,
+        )
+        .ok_or(GetPriceError::PriceOverflow)
+    }
+}
+
+/// Requirements an oracle price must meet to be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PricePolicy {
+    /// Maximum age of the price in seconds
+    pub max_age_s: u64,
+    /// Maximum age of the price in slots
+    pub max_age_slots: u64,
+    /// Maximum ratio of the confidence interval to the price, in fp32
+    pub max_conf_ratio_fp32: u64,
+    pub min_verification_level: VerificationLevel,
+    /// Number of confidence intervals between the price and its bounds, in fp32
+    pub conf_multiplier_fp32: u64,
+}
+
+impl PricePolicy {
+    /// A policy which only checks the age in seconds and requires full verification, with
+    /// bounds one confidence interval away from the price
+    pub const fn new(max_age_s: u64) -> Self {
+        Self {
+            max_age_s,
+            max_age_slots: u64::MAX,
+            max_conf_ratio_fp32: u64::MAX,
+            min_verification_level: VerificationLevel::Full,
+            conf_multiplier_fp32: 1 << 32,
+        }
+    }
+
+    /// Checks that `price` satisfies the policy
+    pub fn check(
+        &self,
+        price: &OraclePrice,
+        verification_level: VerificationLevel,
+        clock: &Clock,
+    ) -> Result<(), GetPriceError> {
+        if !verification_level.gte(self.min_verification_level) {
+            msg!("The price update is not verified enough");
+            return Err(GetPriceError::InsufficientVerificationLevel);
+        }
+        if !price.is_no_older_than(clock, self.max_age_s) {
+            msg!("The oracle price is too old");
+            return Err(GetPriceError::PriceTooOld);
+        }
+        if clock.slot.saturating_sub(price.slot) > self.max_age_slots {
+            msg!("The oracle price was published too many slots ago");
+            return Err(GetPriceError::PriceTooOldInSlots);
+        }
+        if price.price < 0 {
+            return Err(GetPriceError::NegativePrice);
+        }
+        // conf / price > max_conf_ratio
+        if (price.conf as u128) << 32 > price.price as u128 * self.max_conf_ratio_fp32 as u128 {
+            msg!("The oracle price confidence interval is too wide");
+            return Err(GetPriceError::ConfidenceTooWide);
+        }
+        Ok(())
+    }
+}
+
+pub trait PriceOracle {
+    /// Returns the latest spot price, without any check
+    fn price(&self) -> Result<OraclePrice, ProgramError>;
+
+    /// Returns the latest EMA price, without any check
+    fn ema_price(&self) -> Result<OraclePrice, ProgramError>;
+
+    /// Returns how thoroughly the oracle data was verified
+    fn verification_level(&self) -> VerificationLevel {
+        VerificationLevel::Full
+    }
+
+    /// Returns the latest spot price if it satisfies `policy`
+    fn price_with_policy(
+        &self,
+        clock: &Clock,
+        policy: &PricePolicy,
+    ) -> Result<OraclePrice, ProgramError> {
+        let price = self.price()?;
+        policy.check(&price, self.verification_level(), clock)?;
+        Ok(price)
+    }
+
+    /// Returns the latest EMA price if it satisfies `policy`
+    fn ema_price_with_policy(
+        &self,
+        clock: &Clock,
+        policy: &PricePolicy,
+    ) -> Result<OraclePrice, ProgramError> {
+        let price = self.ema_price()?;
+        policy.check(&price, self.verification_level(), clock)?;
+        Ok(price)
+    }
+
+    /// Returns the latest fully verified spot price if it was published at most `max_age_s`
+    /// seconds ago
+    fn price_no_older_than(
+        &self,
+        clock: &Clock,
+        max_age_s: u64,
+    ) -> Result<OraclePrice, ProgramError> {
+        self.price_with_policy(clock, &PricePolicy::new(max_age_s))
+    }
+
+    /// Returns the latest fully verified EMA price if it was published at most `max_age_s`
+    /// seconds ago
+    fn ema_price_no_older_than(
+        &self,
+        clock: &Clock,
+        max_age_s: u64,
+    ) -> Result<OraclePrice, ProgramError> {
+        self.ema_price_with_policy(clock, &PricePolicy::new(max_age_s))
+    }
+}
+
+impl PriceOracle for SolanaPriceAccount {
+    fn price(&self) -> Result<OraclePrice, ProgramError> {
+        // The aggregate is only valid while trading, otherwise fall back on the previous one
+        let price = if self.agg.status == PriceStatus::Trading {
+            OraclePrice {
+                price: self.agg.price,
+                conf: self.agg.conf,
+                exponent: self.expo,
+                publish_time: self.timestamp,
+                slot: self.agg.pub_slot,
+            }
+        } else {
+            OraclePrice {
+                price: self.prev_price,
+                conf: self.prev_conf,
+                exponent: self.expo,
+                publish_time: self.prev_timestamp,
+                slot: self.prev_slot,
+            }
+        };
+        Ok(price)
+    }
+
+    fn ema_price(&self) -> Result<OraclePrice, ProgramError> {
+        let spot = self.price()?;
+        Ok(OraclePrice {
+            price: self.ema_price.val,
+            conf: self.ema_conf.val as u64,
+            ..spot
+        })
+    }
+}
+
+/// The feed id of the update is not checked, this must be done by the caller.
+impl PriceOracle for PriceUpdateV2 {
+    fn price(&self) -> Result<OraclePrice, ProgramError> {
+        Ok(OraclePrice {
+            price: self.price_message.price,
+            conf: self.price_message.conf,
+            exponent: self.price_message.exponent,
+            publish_time: self.price_message.publish_time,
+            slot: self.posted_slot,
+        })
+    }
+
+    fn ema_price(&self) -> Result<OraclePrice, ProgramError> {
+        let spot = self.price()?;
+        Ok(OraclePrice {
+            price: self.price_message.ema_price,
+            conf: self.price_message.ema_conf,
+            ..spot
+        })
+    }
+
+    fn verification_level(&self) -> VerificationLevel {
+        self.verification_level
+    }
+}
+
+/// Owner of mock oracle accounts
+pub const MOCK_ORACLE_OWNER: Pubkey = pubkey!("MockPrice1111111111111111111111111111111111");
+
+/// Discriminator of mock oracle accounts
+pub const MOCK_ORACLE_DISCRIMINATOR: [u8; 8] = *b"mockorcl";
+
+/// A mock oracle meant for tests.
+///
+/// The account data is [`MOCK_ORACLE_DISCRIMINATOR`] followed by the bytes of this struct.
+#[derive(Clone, Copy, Zeroable, Pod, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct MockOracle {
+    pub price: i64,
+    pub conf: u64,
+    pub ema_price: i64,
+    pub ema_conf: u64,
+    pub publish_time: i64,
+    pub slot: u64,
+    pub exponent: i32,
+    pub _padding: [u8; 4],
+}
+
+impl MockOracle {
+    pub const LEN: usize = 8 + std::mem::size_of::<Self>();
+
+    /// Creates a mock oracle whose spot and EMA prices are both `price * 10^exponent`
+    pub fn new(price: i64, exponent: i32, publish_time: i64) -> Self {
+        Self {
+            price,
+            conf: 0,
+            ema_price: price,
+            ema_conf: 0,
+            publish_time,
+            slot: 0,
+            exponent,
+            _padding: [0; 4],
+        }
+    }
+
+    /// Returns true if `data` starts with [`MOCK_ORACLE_DISCRIMINATOR`]
+    pub fn is_mock_account(data: &[u8]) -> bool {
+        data.starts_with(&MOCK_ORACLE_DISCRIMINATOR)
+    }
+
+    /// Returns true if `account` is owned by [`MOCK_ORACLE_OWNER`] and has the mock layout
+    pub fn is_mock_account_info(account: &AccountInfo) -> bool {
+        account.owner == &MOCK_ORACLE_OWNER
+            && account
+                .try_borrow_data()
+                .map(|data| Self::is_mock_account(&data))
+                .unwrap_or(false)
+    }
+
+    pub fn from_account_info(account: &AccountInfo) -> Result<Self, ProgramError> {
+        if account.owner != &MOCK_ORACLE_OWNER {
+            msg!(
+                "Mock oracle accounts must be owned by {}",
+                MOCK_ORACLE_OWNER
+            );
+            return Err(GetPriceError::InvalidPriceAccountOwner.into());
+        }
+        Self::from_account_data(&account.try_borrow_data()?)
+    }
+
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        if !Self::is_mock_account(data) || data.len() != Self::LEN {
+            msg!("Invalid mock oracle account");
+            return Err(GetPriceError::InvalidPriceAccount.into());
+        }
+        Ok(bytemuck::pod_read_unaligned(&data[8..]))
+    }
+
+    pub fn to_account_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(Self::LEN);
+        data.extend_from_slice(&MOCK_ORACLE_DISCRIMINATOR);
+        data.extend_from_slice(bytemuck::bytes_of(self));
+        data
+    }
+}
+
+impl PriceOracle for MockOracle {
+    fn price(&self) -> Result<OraclePrice, ProgramError> {
+        Ok(OraclePrice {
+            price: self.price,
+            conf: self.conf,
+            exponent: self.exponent,
+            publish_time: self.publish_time,
+            slot: self.slot,
+        })
+    }
+
+    fn ema_price(&self) -> Result<OraclePrice, ProgramError> {
+        Ok(OraclePrice {
+            price: self.ema_price,
+            conf: self.ema_conf,
+            ..self.price()?
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pyth::utils::PriceFeedMessage;
+    use solana_program::pubkey::Pubkey;
+
+    fn clock(ts: i64) -> Clock {
+        Clock {
+            unix_timestamp: ts,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_mock() {
+        let mut oracle = MockOracle::new(14_845_000_000, -8, 100);
+        oracle.ema_price = 14_000_000_000;
+        let data = oracle.to_account_data();
+        assert_eq!(data.len(), MockOracle::LEN);
+        let oracle = MockOracle::from_account_data(&data).unwrap();
+
+        let price = oracle.price_no_older_than(&clock(160), 60).unwrap();
+        assert_eq!(
+            price.to_fp32(9, 6),
+            Ok((148_450u128 << 32) as u64 / 1_000_000)
+        );
+        assert_eq!(oracle.ema_price().unwrap().price, 14_000_000_000);
+        assert_eq!(
+            oracle.price_no_older_than(&clock(161), 60),
+            Err(GetPriceError::PriceTooOld.into())
+        );
+        assert!(oracle
+            .ema_price_no_older_than(&clock(161), u64::MAX)
+            .is_ok());
+
+        assert!(MockOracle::from_account_data(&data[..40]).is_err());
+        assert!(MockOracle::from_account_data(&[0; MockOracle::LEN]).is_err());
+
+        let negative = OraclePrice { price: -1, ..price };
+        assert_eq!(negative.to_fp32(9, 6), Err(GetPriceError::NegativePrice));
+        let huge = OraclePrice {
+            exponent: 20,
+            ..price
+        };
+        assert_eq!(huge.to_fp32(9, 6), Err(GetPriceError::PriceOverflow));
+    }
+
+    #[test]
+    fn test_policy() {
+        // 100.00 ± 1.00
+        let price = OraclePrice {
+            price: 10_000,
+            conf: 100,
+            exponent: -2,
+            publish_time: 100,
+            slot: 50,
+        };
+        assert_eq!(
+            price.bound_fp32(PriceBound::Lower, 1 << 32, 0, 0),
+            Ok(99 << 32)
+        );
+        assert_eq!(
+            price.bound_fp32(PriceBound::Upper, 1 << 32, 0, 0),
+            Ok(101 << 32)
+        );
+        assert_eq!(
+            price.bound_fp32(PriceBound::Lower, 5 << 31, 0, 0),
+            Ok((975 << 32) / 10)
+        );
+        assert_eq!(
+            price.bound_fp64(PriceBound::Upper, 1 << 32, 0, 0),
+            Ok(101 << 64)
+        );
+        assert_eq!(price.to_fp64(0, 0), Ok(100 << 64));
+        assert_eq!(price.bound_fp32(PriceBound::Lower, 200 << 32, 0, 0), Ok(0));
+
+        // Bounds are rounded away from the price
+        let tenth = OraclePrice {
+            price: 1,
+            conf: 0,
+            exponent: -1,
+            ..price
+        };
+        assert_eq!(
+            tenth.bound_fp32(PriceBound::Lower, 0, 0, 0),
+            Ok(429_496_729)
+        );
+        assert_eq!(
+            tenth.bound_fp32(PriceBound::Upper, 0, 0, 0),
+            Ok(429_496_730)
+        );
+
+        let clock = Clock {
+            unix_timestamp: 110,
+            slot: 60,
+            ..Default::default()
+        };
+        let policy = PricePolicy {
+            max_age_slots: 10,
+            max_conf_ratio_fp32: (1 << 32) / 50,
+            ..PricePolicy::new(10)
+        };
+        assert_eq!(
+            policy.check(&price, VerificationLevel::Full, &clock),
+            Ok(())
+        );
+        let strict = PricePolicy {
+            max_age_slots: 9,
+            ..policy
+        };
+        assert_eq!(
+            strict.check(&price, VerificationLevel::Full, &clock),
+            Err(GetPriceError::PriceTooOldInSlots)
+        );
+        let strict = PricePolicy {
+            max_conf_ratio_fp32: (1 << 32) / 200,
+            ..policy
+        };
+        assert_eq!(
+            strict.check(&price, VerificationLevel::Full, &clock),
+            Err(GetPriceError::ConfidenceTooWide)
+        );
+        assert_eq!(
+            policy.check(
+                &price,
+                VerificationLevel::Partial { num_signatures: 5 },
+                &clock
+            ),
+            Err(GetPriceError::InsufficientVerificationLevel)
+        );
+    }
+
+    #[test]
+    fn test_pyth_legacy() {
+        let mut account = SolanaPriceAccount {
+            expo: -8,
+            timestamp: 100,
+            prev_timestamp: 90,
+            prev_price: 5,
+            prev_slot: 7,
+            ..Default::default()
+        };
+        account.agg.price = 10;
+        account.agg.pub_slot = 8;
+        account.ema_price.val = 9;
+        assert_eq!(account.price().unwrap().price, 5);
+        assert_eq!(account.price().unwrap().slot, 7);
+
+        account.agg.status = PriceStatus::Trading;
+        let price = account.price().unwrap();
+        assert_eq!((price.price, price.publish_time, price.slot), (10, 100, 8));
+        let ema = account.ema_price_no_older_than(&clock(100), 0).unwrap();
+        assert_eq!((ema.price, ema.publish_time), (9, 100));
+    }
+
+    #[test]
+    fn test_pyth_v2() {
+        let mut update = PriceUpdateV2 {
+            write_authority: Pubkey::default(),
+            verification_level: VerificationLevel::Full,
+            price_message: PriceFeedMessage {
+                feed_id: [0; 32],
+                price: 10,
+                conf: 1,
+                exponent: -2,
+                publish_time: 100,
+                prev_publish_time: 90,
+                ema_price: 9,
+                ema_conf: 2,
+            },
+            posted_slot: 3,
+        };
+        let price = update.price_no_older_than(&clock(110), 10).unwrap();
+        assert_eq!(
+            price,
+            OraclePrice {
+                price: 10,
+                conf: 1,
+                exponent: -2,
+                publish_time: 100,
+                slot: 3
+            }
+        );
+        assert_eq!(update.ema_price().unwrap().conf, 2);
+        assert!(update.price_no_older_than(&clock(111), 10).is_err());
+
+        update.verification_level = VerificationLevel::Partial { num_signatures: 5 };
+        assert_eq!(
+            update.price_no_older_than(&clock(110), 10),
+            Err(GetPriceError::InsufficientVerificationLevel.into())
+        );
+        let policy = PricePolicy {
+            min_verification_level: VerificationLevel::Partial { num_signatures: 3 },
+            ..PricePolicy::new(10)
+        };
+        assert!(update.price_with_policy(&clock(110), &policy).is_ok());
+    }
+}
@@ -0,0 +1,244 @@
+//! Combining several oracle prices: cross rates, medians and fallbacks.
+use solana_program::{clock::Clock, msg, program_error::ProgramError};
+use std::convert::TryFrom;
+
+use super::{OraclePrice, PriceOracle, PricePolicy};
+use crate::{
+    decimals::pow10,
+    fp_math::{mul_div_ceil, mul_div_floor},
+    pyth::utils::GetPriceError,
+};
+
+/// Returns the price of `base` in `quote` given the prices of both in a common currency, e.g.
+/// mSOL/USDC from mSOL/USD and USDC/USD.
+///
+/// The price is rounded down and keeps as many significant digits as an `i64` allows. The
+/// confidence is propagated as `price * (base.conf / base.price + quote.conf / quote.price)`,
+/// rounded up. The publish time and slot are those of the oldest input.
+pub fn cross_rate(base: &OraclePrice, quote: &OraclePrice) -> Result<OraclePrice, GetPriceError> {
+    if base.price < 0 || quote.price < 0 {
+        return Err(GetPriceError::NegativePrice);
+    }
+    if quote.price == 0 {
+        return Err(GetPriceError::ZeroPrice);
+    }
+    let (base_price, quote_price) = (base.price as u128, quote.price as u128);
+
+    // Scale the numerator as much as possible, then drop the digits which do not fit in an i64
+    let mut shift = 0;
+    let mut numerator = base_price;
+    while let Some(n) = numerator
+        .checked_mul(10)
+        .filter(|_| base_price != 0 && shift < 38)
+    {
+        numerator = n;
+        shift += 1;
+    }
+    let mut price = numerator / quote_price;
+    while price > i64::MAX as u128 {
+        price /= 10;
+        shift -= 1;
+    }
+
+    // conf = (base.conf + price * quote.conf) / quote.price in the scaled unit
+    let scale = pow10(shift).ok_or(GetPriceError::PriceOverflow)?;
+    let conf = mul_div_ceil(base.conf as u128, scale, quote_price)
+        .zip(mul_div_ceil(price, quote.conf as u128, quote_price))
+        .and_then(|(a, b)| a.checked_add(b))
+        .and_then(|c| u64::try_from(c).ok())
+        .ok_or(GetPriceError::PriceOverflow)?;
+    let exponent = base
+        .exponent
+        .checked_sub(quote.exponent)
+        .and_then(|e| e.checked_sub(shift as i32))
+        .ok_or(GetPriceError::PriceOverflow)?;
+
+    Ok(OraclePrice {
+        price: price as i64,
+        conf,
+        exponent,
+        publish_time: base.publish_time.min(quote.publish_time),
+        slot: base.slot.min(quote.slot),
+    })
+}
+
+/// Returns the median of `prices`, failing if any of them deviates from it by more than the
+/// fp32 ratio `max_deviation_fp32`.
+///
+/// For an even number of prices the two middle prices are averaged, rounding down, and the
+/// larger of their confidences is used. The result uses the smallest exponent of the inputs and
+/// the publish time and slot of the oldest input.
+pub fn median(
+    prices: &[OraclePrice],
+    max_deviation_fp32: u64,
+) -> Result<OraclePrice, GetPriceError> {
+    let exponent = prices
+        .iter()
+        .map(|p| p.exponent)
+        .min()
+        .ok_or(GetPriceError::NotEnoughPrices)?;
+    let mut values = prices
+        .iter()
+        .map(|p| normalize(p, exponent))
+        .collect::<Result<Vec<_>, _>>()?;
+    values.sort_unstable();
+
+    let middle = values.len() / 2;
+    let (price, conf) = if values.len() % 2 == 1 {
+        values[middle]
+    } else {
+        let ((low, low_conf), (high, high_conf)) = (values[middle - 1], values[middle]);
+        (low + (high - low) / 2, low_conf.max(high_conf))
+    };
+
+    let threshold = mul_div_floor(price, max_deviation_fp32 as u128, 1 << 32)
+        .ok_or(GetPriceError::PriceOverflow)?;
+    if values.iter().any(|(v, _)| v.abs_diff(price) > threshold) {
+        msg!("An oracle price deviates too much from the median");
+        return Err(GetPriceError::PriceDeviationTooHigh);
+    }
+
+    Ok(OraclePrice {
+        price: i64::try_from(price).map_err(|_| GetPriceError::PriceOverflow)?,
+        conf: u64::try_from(conf).map_err(|_| GetPriceError::PriceOverflow)?,
+        exponent,
+        publish_time: prices.iter().map(|p| p.publish_time).min().unwrap(),
+        slot: prices.iter().map(|p| p.slot).min().unwrap(),
+    })
+}
+
+/// Returns the price and confidence of `price` with the smaller exponent `exponent`
+fn normalize(price: &OraclePrice, exponent: i32) -> Result<(u128, u128), GetPriceError> {
+    if price.price < 0 {
+        return Err(GetPriceError::NegativePrice);
+    }
+    let factor = u32::try_from(price.exponent as i64 - exponent as i64)
+        .ok()
+        .and_then(pow10)
+        .ok_or(GetPriceError::PriceOverflow)?;
+    (price.price as u128)
+        .checked_mul(factor)
+        .zip((price.conf as u128).checked_mul(factor))
+        .ok_or(GetPriceError::PriceOverflow)
+}
+
+/// Returns the spot price of the first of `sources` which satisfies `policy`, or the error of
+/// the last one
+pub fn price_with_fallback(
+    sources: &[&dyn PriceOracle],
+    clock: &Clock,
+    policy: &PricePolicy,
+) -> Result<OraclePrice, ProgramError> {
+    let mut error = GetPriceError::NotEnoughPrices.into();
+    for (i, source) in sources.iter().enumerate() {
+        match source.price_with_policy(clock, policy) {
+            Ok(price) => return Ok(price),
+            Err(e) => {
+                msg!("Oracle source {} was rejected: {}", i, e);
+                error = e;
+            }
+        }
+    }
+    Err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oracle::MockOracle;
+
+    fn price(price: i64, conf: u64, exponent: i32) -> OraclePrice {
+        OraclePrice {
+            price,
+            conf,
+            exponent,
+            publish_time: 100,
+            slot: 10,
+        }
+    }
+
+    #[test]
+    fn test_cross_rate() {
+        // mSOL/USD = 180 ± 0.18 and USDC/USD = 0.9999 ± 0.0001
+        let msol = price(18_000_000_000, 18_000_000, -8);
+        let usdc = OraclePrice {
+            publish_time: 90,
+            ..price(99_990_000, 10_000, -8)
+        };
+        let rate = cross_rate(&msol, &usdc).unwrap();
+        assert_eq!(rate.publish_time, 90);
+        let value = rate.price as f64 * 10f64.powi(rate.exponent);
+        assert!((value - 180.0 / 0.9999).abs() < 1e-12);
+        // 0.1% + 0.01% relative confidence
+        let conf = rate.conf as f64 / rate.price as f64;
+        assert!((conf - (0.001 + 0.0001 / 0.9999)).abs() < 1e-12);
+
+        // MSol in USDC with 9 and 6 decimals
+        assert_eq!(
+            rate.to_fp32(9, 6).unwrap(),
+            ((180u128 << 32) * 10_000 / 9_999 / 1_000) as u64
+        );
+
+        assert_eq!(
+            cross_rate(&msol, &price(0, 0, -8)),
+            Err(GetPriceError::ZeroPrice)
+        );
+        assert_eq!(
+            cross_rate(&price(-1, 0, -8), &usdc),
+            Err(GetPriceError::NegativePrice)
+        );
+        assert_eq!(
+            cross_rate(&price(0, 5, 0), &price(1, 0, 0)).unwrap().price,
+            0
+        );
+        let max = cross_rate(&price(i64::MAX, 0, 0), &price(1, 0, 0)).unwrap();
+        assert_eq!((max.price, max.exponent), (i64::MAX, 0));
+    }
+
+    #[test]
+    fn test_median() {
+        let prices = [price(101, 1, 0), price(1_000, 2, -1), price(1_020, 5, -1)];
+        let m = median(&prices, 1 << 32).unwrap();
+        assert_eq!((m.price, m.conf, m.exponent), (1_010, 10, -1));
+        assert_eq!(
+            median(&prices, (1 << 32) / 200),
+            Err(GetPriceError::PriceDeviationTooHigh)
+        );
+        assert!(median(&prices, (1 << 32) / 100).is_ok());
+
+        let m = median(&prices[1..], 1 << 32).unwrap();
+        assert_eq!((m.price, m.conf), (1_010, 5));
+        assert_eq!(median(&[], 0), Err(GetPriceError::NotEnoughPrices));
+        assert_eq!(
+            median(&[price(1, 0, 0), price(1, 0, -40)], 0),
+            Err(GetPriceError::PriceOverflow)
+        );
+    }
+
+    #[test]
+    fn test_fallback() {
+        let clock = Clock {
+            unix_timestamp: 200,
+            ..Default::default()
+        };
+        let stale = MockOracle::new(1, 0, 100);
+        let fresh = MockOracle::new(2, 0, 190);
+        let policy = PricePolicy::new(20);
+        let price = price_with_fallback(&[&stale, &fresh], &clock, &policy).unwrap();
+        assert_eq!(price.price, 2);
+        assert_eq!(
+            price_with_fallback(&[&fresh, &stale], &clock, &policy)
+                .unwrap()
+                .price,
+            2
+        );
+        assert_eq!(
+            price_with_fallback(&[&stale], &clock, &policy),
+            Err(GetPriceError::PriceTooOld.into())
+        );
+        assert_eq!(
+            price_with_fallback(&[], &clock, &policy),
+            Err(GetPriceError::NotEnoughPrices.into())
+        );
+    }
+}
@@ -1,25 +1,33 @@
-use crate::{checks::check_account_owner, tokens::SupportedToken};
-use borsh::BorshDeserialize;
-use pyth_sdk_solana::{
-    state::{
-        load_mapping_account, load_product_account, CorpAction, PriceStatus, PriceType,
-        SolanaPriceAccount,
+#[cfg(feature = "mock-oracle")]
+use crate::oracle::MockOracle;
+use crate::{
+    checks::check_account_owner,
+    oracle::{
+        aggregate::{cross_rate, median},
+        OraclePrice, PriceBound, PriceOracle, PricePolicy,
     },
-    Price,
+    tokens::SupportedToken,
+};
+use borsh::BorshDeserialize;
+use pyth_sdk_solana::state::{
+    load_mapping_account, load_price_account, load_product_account, CorpAction, PriceStatus,
+    PriceType, SolanaPriceAccount,
 };
 use solana_program::{
     account_info::AccountInfo, clock::Clock, msg, program_error::ProgramError, pubkey::Pubkey,
 };
 use solana_program::{pubkey, sysvar::Sysvar};
-use std::convert::TryInto;
 
+pub mod resolver;
 pub mod utils;
 
-use utils::PriceUpdateV2;
+use resolver::{FeedResolver, SupportedTokenResolver};
+use utils::{FeedId, GetPriceError, PriceUpdateV2, TwapUpdate};
 
 pub const DEFAULT_PYTH_PUSH: Pubkey = pubkey!("pyt2F414BA6dPttK6RddPZUdHfapoBN24GL5wbrPCou");
 pub const PYTH_RECEIVER: Pubkey = pubkey!("rec2HHDDnjLfj4kE7VyEtFA1HPGQLK33259532cRyHp");
 pub const PRICE_FEED_DISCRIMATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+pub const TWAP_UPDATE_DISCRIMINATOR: [u8; 8] = [104, 192, 188, 72, 246, 166, 12, 81];
 
 pub fn check_price_acc_key(
     mapping_acc_data: &[u8],
@@ -28,7 +36,10 @@ pub fn check_price_acc_key(
     price_acc_key: &Pubkey,
 ) -> Result<(), ProgramError> {
     // Only checking the first mapping account
-    let map_acct = load_mapping_account(mapping_acc_data).unwrap();
+    let map_acct = load_mapping_account(mapping_acc_data).map_err(|_| {
+        msg!("Invalid pyth mapping account");
+        GetPriceError::InvalidPriceAccount
+    })?;
 
     // Get and print each Product in Mapping directory
     for prod_key in &map_acct.products {
@@ -37,7 +48,10 @@ pub fn check_price_acc_key(
         }
         msg!("Found product in mapping.");
 
-        let prod_acc = load_product_account(product_acc_data).unwrap();
+        let prod_acc = load_product_account(product_acc_data).map_err(|_| {
+            msg!("Invalid pyth product account");
+            GetPriceError::InvalidPriceAccount
+        })?;
 
         if prod_acc.px_acc == Pubkey::default() {
             msg!("Price account is invalid.");
@@ -52,84 +66,109 @@ pub fn check_price_acc_key(
     }
 
     msg!("Could not find product in mapping.");
-    Err(ProgramError::InvalidArgument)
+    Err(GetPriceError::PriceAccountNotFound.into())
 }
 
-pub fn get_oracle_price_fp32(
+/// Runs `f` on the legacy Pyth price account `price_account_info`, or on a mock oracle if the
+/// `mock-oracle` feature is enabled and the account is a mock oracle account
+fn with_legacy_oracle<R>(
     price_account_info: &AccountInfo,
-    no_older_than_s: u64,
-    base_decimals: u8,
-    quote_decimals: u8,
-) -> Result<u64, ProgramError> {
+    f: impl FnOnce(&dyn PriceOracle) -> Result<R, ProgramError>,
+) -> Result<R, ProgramError> {
     #[cfg(feature = "mock-oracle")]
     {
-        // Mock testing oracle
-        if account_data.len() == 8 {
-            return Ok(u64::from_le_bytes(account_data[0..8].try_into().unwrap()));
+        if MockOracle::is_mock_account_info(price_account_info) {
+            return f(&MockOracle::from_account_info(price_account_info)?);
         }
-    };
-
-    // Pyth Oracle
-    let price_account = SolanaPriceAccount::account_info_to_feed(price_account_info)?;
-    // load_price_account(account_data)?;
-    let Price { price, expo, .. } = price_account
-        .get_price_no_older_than(Clock::get()?.unix_timestamp, no_older_than_s)
-        .ok_or_else(|| {
-            msg!("Cannot parse pyth price, information unavailable.");
-            ProgramError::InvalidAccountData
-        })?;
-    let price = if expo > 0 {
-        ((price as u128) << 32) * 10u128.pow(expo as u32)
-    } else {
-        ((price as u128) << 32) / 10u128.pow((-expo) as u32)
-    };
+    }
+    let data = price_account_info.try_borrow_data()?;
+    let price_account: &SolanaPriceAccount = load_price_account(&data).map_err(|_| {
+        msg!("Cannot parse pyth price account");
+        GetPriceError::InvalidPriceAccount
+    })?;
+    f(price_account)
+}
 
-    let corrected_price =
-        (price * 10u128.pow(quote_decimals as u32)) / 10u128.pow(base_decimals as u32);
+/// Runs `f` on the Pyth pull oracle update `account` after checking that it is for `feed_id`, or
+/// on a mock oracle if the `mock-oracle` feature is enabled and the account is a mock oracle
+/// account
+pub(crate) fn with_pull_oracle<R>(
+    feed_id: &FeedId,
+    account: &AccountInfo,
+    f: impl FnOnce(&dyn PriceOracle) -> Result<R, ProgramError>,
+) -> Result<R, ProgramError> {
+    #[cfg(feature = "mock-oracle")]
+    {
+        if MockOracle::is_mock_account_info(account) {
+            return f(&MockOracle::from_account_info(account)?);
+        }
+    }
+    check_account_owner(account, &PYTH_RECEIVER)
+        .map_err(|_| GetPriceError::InvalidPriceAccountOwner)?;
+    let data = account.try_borrow_data()?;
+    let update = parse_price_v2(&data)?;
+    if update.price_message.feed_id != *feed_id {
+        msg!("The price update does not match the requested feed id");
+        return Err(GetPriceError::MismatchedFeedId.into());
+    }
+    f(&update)
+}
+
+fn check_price_feed_account_key(
+    account: &AccountInfo,
+    shard: u16,
+    feed_id: &FeedId,
+) -> Result<(), ProgramError> {
+    #[cfg(feature = "mock-oracle")]
+    {
+        if MockOracle::is_mock_account_info(account) {
+            return Ok(());
+        }
+    }
+    if *account.key != get_pyth_feed_account_key(shard, feed_id) {
+        msg!(
+            "The price account is not the feed account of shard {}",
+            shard
+        );
+        return Err(GetPriceError::MismatchedPriceAccount.into());
+    }
+    Ok(())
+}
 
-    let final_price = corrected_price.try_into().unwrap();
+fn to_fp32(price: OraclePrice, base_decimals: u8, quote_decimals: u8) -> Result<u64, ProgramError> {
+    let final_price = price
+        .to_fp32(base_decimals, quote_decimals)
+        .inspect_err(|e| msg!("Cannot convert the pyth price: {}", e))?;
 
     msg!("Pyth FP32 price value: {:?}", final_price);
 
     Ok(final_price)
 }
 
-pub fn get_oracle_ema_price_fp32(
+pub fn get_oracle_price_fp32(
     price_account_info: &AccountInfo,
     no_older_than_s: u64,
     base_decimals: u8,
     quote_decimals: u8,
 ) -> Result<u64, ProgramError> {
-    #[cfg(feature = "mock-oracle")]
-    {
-        // Mock testing oracle
-        if account_data.len() == 8 {
-            return Ok(u64::from_le_bytes(account_data[0..8].try_into().unwrap()));
-        }
-    };
-
-    // Pyth Oracle
-    let price_account = SolanaPriceAccount::account_info_to_feed(price_account_info)?;
-    let Price { price, expo, .. } = price_account
-        .get_ema_price_no_older_than(Clock::get()?.unix_timestamp, no_older_than_s)
-        .ok_or_else(|| {
-            msg!("Cannot parse pyth ema price, information unavailable.");
-            ProgramError::InvalidAccountData
-        })?;
-    let price = if expo > 0 {
-        ((price as u128) << 32) * 10u128.pow(expo as u32)
-    } else {
-        ((price as u128) << 32) / 10u128.pow((-expo) as u32)
-    };
-
-    let corrected_price =
-        (price * 10u128.pow(quote_decimals as u32)) / 10u128.pow(base_decimals as u32);
-
-    let final_price = corrected_price.try_into().unwrap();
-
-    msg!("Pyth FP32 price value: {:?}", final_price);
+    let clock = Clock::get()?;
+    let price = with_legacy_oracle(price_account_info, |oracle| {
+        oracle.price_no_older_than(&clock, no_older_than_s)
+    })?;
+    to_fp32(price, base_decimals, quote_decimals)
+}
 
-    Ok(final_price)
+pub fn get_oracle_ema_price_fp32(
+    price_account_info: &AccountInfo,
+    no_older_than_s: u64,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Result<u64, ProgramError> {
+    let clock = Clock::get()?;
+    let price = with_legacy_oracle(price_account_info, |oracle| {
+        oracle.ema_price_no_older_than(&clock, no_older_than_s)
+    })?;
+    to_fp32(price, base_decimals, quote_decimals)
 }
 
 pub fn get_oracle_price_or_ema_fp32(
@@ -138,48 +177,72 @@ pub fn get_oracle_price_or_ema_fp32(
     base_decimals: u8,
     quote_decimals: u8,
 ) -> Result<u64, ProgramError> {
-    #[cfg(feature = "mock-oracle")]
-    {
-        // Mock testing oracle
-        if account_data.len() == 8 {
-            return Ok(u64::from_le_bytes(account_data[0..8].try_into().unwrap()));
-        }
-    };
-
-    // Pyth Oracle
-    let price_feed = SolanaPriceAccount::account_info_to_feed(price_account_info)?;
-    let unix_timestamp = Clock::get()?.unix_timestamp;
-    let Price { price, expo, .. } = price_feed
-        .get_price_no_older_than(unix_timestamp, no_older_than_s)
-        .or_else(|| {
-            msg!("Cannot parse pyth price, information unavailable. Fallback on EMA");
-            price_feed.get_ema_price_no_older_than(unix_timestamp, no_older_than_s)
-        })
-        .unwrap();
-    let price = if expo > 0 {
-        ((price as u128) << 32) * 10u128.pow(expo as u32)
-    } else {
-        ((price as u128) << 32) / 10u128.pow((-expo) as u32)
-    };
-
-    let corrected_price =
-        (price * 10u128.pow(quote_decimals as u32)) / 10u128.pow(base_decimals as u32);
-
-    let final_price = corrected_price.try_into().unwrap();
+    let clock = Clock::get()?;
+    let price = with_legacy_oracle(price_account_info, |oracle| {
+        oracle
+            .price_no_older_than(&clock, no_older_than_s)
+            .or_else(|_| {
+                msg!("Cannot parse pyth price, information unavailable. Fallback on EMA");
+                oracle.ema_price_no_older_than(&clock, no_older_than_s)
+            })
+    })?;
+    to_fp32(price, base_decimals, quote_decimals)
+}
 
-    msg!("Pyth FP32 price value: {:?}", final_price);
+/// Returns the `bound` of the spot price in fp32 if it satisfies `policy`
+pub fn get_oracle_price_bound_fp32(
+    price_account_info: &AccountInfo,
+    policy: &PricePolicy,
+    bound: PriceBound,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Result<u64, ProgramError> {
+    let clock = Clock::get()?;
+    let price = with_legacy_oracle(price_account_info, |oracle| {
+        oracle.price_with_policy(&clock, policy)
+    })?;
+    let final_price = price.bound_fp32(
+        bound,
+        policy.conf_multiplier_fp32,
+        base_decimals,
+        quote_decimals,
+    )?;
+    msg!("Pyth FP32 price bound: {:?}", final_price);
+    Ok(final_price)
+}
 
+/// Returns the `bound` of the spot price in fp64 if it satisfies `policy`
+pub fn get_oracle_price_bound_fp64(
+    price_account_info: &AccountInfo,
+    policy: &PricePolicy,
+    bound: PriceBound,
+    base_decimals: u8,
+    quote_decimals: u8,
+) -> Result<u128, ProgramError> {
+    let clock = Clock::get()?;
+    let price = with_legacy_oracle(price_account_info, |oracle| {
+        oracle.price_with_policy(&clock, policy)
+    })?;
+    let final_price = price.bound_fp64(
+        bound,
+        policy.conf_multiplier_fp32,
+        base_decimals,
+        quote_decimals,
+    )?;
+    msg!("Pyth FP64 price bound: {:?}", final_price);
     Ok(final_price)
 }
 
 pub fn parse_price_v2(data: &[u8]) -> Result<PriceUpdateV2, ProgramError> {
-    let tag = &data[..8];
-
-    if tag != PRICE_FEED_DISCRIMATOR {
-        return Err(ProgramError::InvalidAccountData);
+    if !data.starts_with(&PRICE_FEED_DISCRIMATOR) {
+        msg!("Invalid price update discriminator");
+        return Err(GetPriceError::InvalidPriceAccount.into());
     }
 
-    let des = PriceUpdateV2::deserialize(&mut &data[8..]).unwrap();
+    let des = PriceUpdateV2::deserialize(&mut &data[8..]).map_err(|_| {
+        msg!("Cannot parse price update");
+        GetPriceError::InvalidPriceAccount
+    })?;
 
     Ok(des)
 }
@@ -193,73 +256,307 @@ pub fn get_oracle_price_fp32_v2(
     clock: &Clock,
     maximum_age: u64,
 ) -> Result<u64, ProgramError> {
-    check_account_owner(account, &PYTH_RECEIVER)?;
+    let (feed_id, _) = SupportedTokenResolver.resolve(token_mint)?;
+
+    get_oracle_price_from_feed_id_fp32(
+        &feed_id,
+        account,
+        base_decimals,
+        quote_decimals,
+        clock,
+        maximum_age,
+    )
+}
 
-    let data = &account.data.borrow() as &[u8];
+/// Same as [`get_oracle_price_fp32_v2`], the feed of `token_mint` being given by `resolver`.
+///
+/// `account` must be the price feed account of the resolved feed and shard, pushed by the
+/// default Pyth sponsor.
+pub fn get_oracle_price_fp32_with_resolver(
+    resolver: &impl FeedResolver,
+    token_mint: &Pubkey,
+    account: &AccountInfo,
+    base_decimals: u8,
+    quote_decimals: u8,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u64, ProgramError> {
+    let (feed_id, shard) = resolver.resolve(token_mint)?;
+    check_price_feed_account_key(account, shard, &feed_id)?;
+
+    get_oracle_price_from_feed_id_fp32(
+        &feed_id,
+        account,
+        base_decimals,
+        quote_decimals,
+        clock,
+        maximum_age,
+    )
+}
 
-    let update = parse_price_v2(data).unwrap();
+// Used for Pyth v2 to allow any feed id without validation, the token/asset validation must be done by the caller program
+pub fn get_oracle_price_from_feed_id_fp32(
+    feed_id: &[u8; 32],
+    account: &AccountInfo,
+    base_decimals: u8,
+    quote_decimals: u8,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u64, ProgramError> {
+    let price = with_pull_oracle(feed_id, account, |oracle| {
+        oracle.price_no_older_than(clock, maximum_age)
+    })?;
+    to_fp32(price, base_decimals, quote_decimals)
+}
 
-    let feed_id = SupportedToken::from_mint(token_mint).unwrap().price_feed();
+/// Returns the `bound` of the spot price of `feed_id` in fp32 if it satisfies `policy`
+pub fn get_oracle_price_bound_from_feed_id_fp32(
+    feed_id: &FeedId,
+    account: &AccountInfo,
+    policy: &PricePolicy,
+    bound: PriceBound,
+    base_decimals: u8,
+    quote_decimals: u8,
+    clock: &Clock,
+) -> Result<u64, ProgramError> {
+    let price = with_pull_oracle(feed_id, account, |oracle| {
+        oracle.price_with_policy(clock, policy)
+    })?;
+    let final_price = price.bound_fp32(
+        bound,
+        policy.conf_multiplier_fp32,
+        base_decimals,
+        quote_decimals,
+    )?;
+    msg!("Pyth FP32 price bound: {:?}", final_price);
+    Ok(final_price)
+}
 
-    let utils::Price {
-        price, exponent, ..
-    } = update
-        .get_price_no_older_than(clock, maximum_age, &feed_id)
-        .unwrap();
+/// Returns the `bound` of the spot price of `feed_id` in fp64 if it satisfies `policy`
+pub fn get_oracle_price_bound_from_feed_id_fp64(
+    feed_id: &FeedId,
+    account: &AccountInfo,
+    policy: &PricePolicy,
+    bound: PriceBound,
+    base_decimals: u8,
+    quote_decimals: u8,
+    clock: &Clock,
+) -> Result<u128, ProgramError> {
+    let price = with_pull_oracle(feed_id, account, |oracle| {
+        oracle.price_with_policy(clock, policy)
+    })?;
+    let final_price = price.bound_fp64(
+        bound,
+        policy.conf_multiplier_fp32,
+        base_decimals,
+        quote_decimals,
+    )?;
+    msg!("Pyth FP64 price bound: {:?}", final_price);
+    Ok(final_price)
+}
 
-    let price = if exponent > 0 {
-        ((price as u128) << 32) * 10u128.pow(exponent as u32)
-    } else {
-        ((price as u128) << 32) / 10u128.pow((-exponent) as u32)
-    };
+pub fn parse_twap_update(data: &[u8]) -> Result<TwapUpdate, ProgramError> {
+    if !data.starts_with(&TWAP_UPDATE_DISCRIMINATOR) {
+        msg!("Invalid TWAP update discriminator");
+        return Err(GetPriceError::InvalidPriceAccount.into());
+    }
 
-    let corrected_price =
-        (price * 10u128.pow(quote_decimals as u32)) / 10u128.pow(base_decimals as u32);
+    let des = TwapUpdate::deserialize(&mut &data[8..]).map_err(|_| {
+        msg!("Cannot parse TWAP update");
+        GetPriceError::InvalidPriceAccount
+    })?;
 
-    let final_price = corrected_price.try_into().unwrap();
+    Ok(des)
+}
 
-    msg!("Pyth FP32 price value: {:?}", final_price);
+/// Returns the TWAP of `feed_id` over `window_seconds` from the Pyth TWAP update `account`.
+///
+/// With the `mock-oracle` feature, mock oracle accounts are accepted and their spot price is
+/// returned as the TWAP.
+fn get_twap(
+    feed_id: &FeedId,
+    account: &AccountInfo,
+    window_seconds: u64,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<OraclePrice, ProgramError> {
+    #[cfg(feature = "mock-oracle")]
+    {
+        if MockOracle::is_mock_account_info(account) {
+            return MockOracle::from_account_info(account)?.price_no_older_than(clock, maximum_age);
+        }
+    }
+    check_account_owner(account, &PYTH_RECEIVER)
+        .map_err(|_| GetPriceError::InvalidPriceAccountOwner)?;
+    let update = parse_twap_update(&account.try_borrow_data()?)?;
+    let twap = update
+        .get_twap_no_older_than(clock, maximum_age, window_seconds, feed_id)
+        .inspect_err(|e| msg!("Cannot get the pyth TWAP: {}", e))?;
+    Ok(OraclePrice {
+        price: twap.price,
+        conf: twap.conf,
+        exponent: twap.exponent,
+        publish_time: twap.end_time,
+        slot: 0,
+    })
+}
 
-    Ok(final_price)
+// Used for Pyth TWAP updates of supported tokens
+pub fn get_oracle_twap_fp32_v2(
+    token_mint: &Pubkey,
+    account: &AccountInfo,
+    window_seconds: u64,
+    base_decimals: u8,
+    quote_decimals: u8,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u64, ProgramError> {
+    let (feed_id, _) = SupportedTokenResolver.resolve(token_mint)?;
+
+    get_oracle_twap_from_feed_id_fp32(
+        &feed_id,
+        account,
+        window_seconds,
+        base_decimals,
+        quote_decimals,
+        clock,
+        maximum_age,
+    )
 }
 
-// Used for Pyth v2 to allow any feed id without validation, the token/asset validation must be done by the caller program
-pub fn get_oracle_price_from_feed_id_fp32(
-    feed_id: &[u8; 32],
+// Used for Pyth TWAP updates of any feed id, the token/asset validation must be done by the caller program
+pub fn get_oracle_twap_from_feed_id_fp32(
+    feed_id: &FeedId,
     account: &AccountInfo,
+    window_seconds: u64,
     base_decimals: u8,
     quote_decimals: u8,
     clock: &Clock,
     maximum_age: u64,
 ) -> Result<u64, ProgramError> {
-    check_account_owner(account, &PYTH_RECEIVER)?;
+    let twap = get_twap(feed_id, account, window_seconds, clock, maximum_age)?;
+    to_fp32(twap, base_decimals, quote_decimals)
+}
 
-    let data = &account.data.borrow() as &[u8];
+// Used for Pyth TWAP updates of any feed id, the token/asset validation must be done by the caller program
+pub fn get_oracle_twap_from_feed_id_fp64(
+    feed_id: &FeedId,
+    account: &AccountInfo,
+    window_seconds: u64,
+    base_decimals: u8,
+    quote_decimals: u8,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u128, ProgramError> {
+    let twap = get_twap(feed_id, account, window_seconds, clock, maximum_age)?;
+    let final_price = twap
+        .to_fp64(base_decimals, quote_decimals)
+        .inspect_err(|e| msg!("Cannot convert the pyth price: {}", e))?;
 
-    let update = parse_price_v2(data).unwrap();
+    msg!("Pyth FP64 price value: {:?}", final_price);
 
-    let utils::Price {
-        price, exponent, ..
-    } = update
-        .get_price_no_older_than(clock, maximum_age, feed_id)
-        .unwrap();
+    Ok(final_price)
+}
 
-    let price = if exponent > 0 {
-        ((price as u128) << 32) * 10u128.pow(exponent as u32)
-    } else {
-        ((price as u128) << 32) / 10u128.pow((-exponent) as u32)
-    };
+/// Returns the price of `base` in `quote` from the pull oracle updates of both tokens, e.g.
+/// mSOL/USDC from the mSOL/USD and USDC/USD feeds. Both prices must satisfy `policy`.
+///
+/// See [`cross_rate`] for the rounding and confidence of the result.
+pub fn get_cross_rate_v2(
+    base: SupportedToken,
+    base_account: &AccountInfo,
+    quote: SupportedToken,
+    quote_account: &AccountInfo,
+    policy: &PricePolicy,
+    clock: &Clock,
+) -> Result<OraclePrice, ProgramError> {
+    let base_price = with_pull_oracle(&base.price_feed(), base_account, |oracle| {
+        oracle.price_with_policy(clock, policy)
+    })?;
+    let quote_price = with_pull_oracle(&quote.price_feed(), quote_account, |oracle| {
+        oracle.price_with_policy(clock, policy)
+    })?;
+    Ok(cross_rate(&base_price, &quote_price)?)
+}
 
-    let corrected_price =
-        (price * 10u128.pow(quote_decimals as u32)) / 10u128.pow(base_decimals as u32);
+/// Returns the fp32 price of one raw `base` token in raw `quote` tokens from the pull oracle
+/// updates of both tokens
+pub fn get_cross_rate_fp32_v2(
+    base: SupportedToken,
+    base_account: &AccountInfo,
+    quote: SupportedToken,
+    quote_account: &AccountInfo,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u64, ProgramError> {
+    let policy = PricePolicy::new(maximum_age);
+    let rate = get_cross_rate_v2(base, base_account, quote, quote_account, &policy, clock)?;
+    to_fp32(rate, base.decimals(), quote.decimals())
+}
 
-    let final_price = corrected_price.try_into().unwrap();
+/// Returns the fp64 price of one raw `base` token in raw `quote` tokens from the pull oracle
+/// updates of both tokens
+pub fn get_cross_rate_fp64_v2(
+    base: SupportedToken,
+    base_account: &AccountInfo,
+    quote: SupportedToken,
+    quote_account: &AccountInfo,
+    clock: &Clock,
+    maximum_age: u64,
+) -> Result<u128, ProgramError> {
+    let policy = PricePolicy::new(maximum_age);
+    let rate = get_cross_rate_v2(base, base_account, quote, quote_account, &policy, clock)?;
+    let final_price = rate
+        .to_fp64(base.decimals(), quote.decimals())
+        .inspect_err(|e| msg!("Cannot convert the pyth price: {}", e))?;
 
-    msg!("Pyth FP32 price value: {:?}", final_price);
+    msg!("Pyth FP64 price value: {:?}", final_price);
 
     Ok(final_price)
 }
 
+/// Returns the median spot price of `feed_id` over several pull oracle updates, see [`median`]
+pub fn get_median_price_from_feed_id(
+    feed_id: &FeedId,
+    accounts: &[AccountInfo],
+    policy: &PricePolicy,
+    max_deviation_fp32: u64,
+    clock: &Clock,
+) -> Result<OraclePrice, ProgramError> {
+    let prices = accounts
+        .iter()
+        .map(|account| {
+            with_pull_oracle(feed_id, account, |oracle| {
+                oracle.price_with_policy(clock, policy)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(median(&prices, max_deviation_fp32)?)
+}
+
+/// Returns the spot price of `feed_id` from the first of `accounts` which satisfies `policy`, or
+/// the error of the last one
+pub fn get_price_with_fallback_from_feed_id(
+    feed_id: &FeedId,
+    accounts: &[AccountInfo],
+    policy: &PricePolicy,
+    clock: &Clock,
+) -> Result<OraclePrice, ProgramError> {
+    let mut error = GetPriceError::NotEnoughPrices.into();
+    for account in accounts {
+        match with_pull_oracle(feed_id, account, |oracle| {
+            oracle.price_with_policy(clock, policy)
+        }) {
+            Ok(price) => return Ok(price),
+            Err(e) => {
+                msg!("Price account {} was rejected: {}", account.key, e);
+                error = e;
+            }
+        }
+    }
+    Err(error)
+}
+
 pub fn get_pyth_feed_account_key(shard: u16, price_feed: &[u8]) -> Pubkey {
     let seeds = &[&shard.to_le_bytes() as &[u8], price_feed];
     let (key, _) = Pubkey::find_program_address(seeds, &DEFAULT_PYTH_PUSH);
@@ -267,7 +564,8 @@ pub fn get_pyth_feed_account_key(shard: u16, price_feed: &[u8]) -> Pubkey {
 }
 
 pub fn get_market_symbol(pyth_product_acc_data: &[u8]) -> Result<&str, ProgramError> {
-    let pyth_product = load_product_account(pyth_product_acc_data).unwrap();
+    let pyth_product = load_product_account(pyth_product_acc_data)
+        .map_err(|_| GetPriceError::InvalidPriceAccount)?;
     for (k, v) in pyth_product.iter() {
         if k == "symbol" {
             return Ok(v);
@@ -451,5 +749,227 @@ mod test {
         )
         .unwrap();
         assert_eq!(565179032, price_fp32);
+
+        let stale_clock = Clock {
+            unix_timestamp: i64::MAX,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_oracle_price_from_feed_id_fp32(&feed, &account_info, 9, 6, &stale_clock, 2 * 60),
+            Err(GetPriceError::PriceTooOld.into())
+        );
+        assert_eq!(
+            get_oracle_price_from_feed_id_fp32(&[0; 32], &account_info, 9, 6, &clock, 2 * 60),
+            Err(GetPriceError::MismatchedFeedId.into())
+        );
+        assert_eq!(
+            get_oracle_price_fp32_v2(&Pubkey::default(), &account_info, 9, 6, &clock, 2 * 60),
+            Err(GetPriceError::UnsupportedToken.into())
+        );
+
+        struct ShardResolver(u16);
+        impl FeedResolver for ShardResolver {
+            fn resolve(&self, _: &Pubkey) -> Result<(FeedId, u16), ProgramError> {
+                Ok((SupportedToken::Sol.price_feed(), self.0))
+            }
+        }
+        let mint = SupportedToken::Sol.mint();
+        assert_eq!(
+            get_oracle_price_fp32_with_resolver(
+                &ShardResolver(0),
+                &mint,
+                &account_info,
+                9,
+                6,
+                &clock,
+                2 * 60
+            ),
+            Ok(565179032)
+        );
+        assert_eq!(
+            get_oracle_price_fp32_with_resolver(
+                &ShardResolver(1),
+                &mint,
+                &account_info,
+                9,
+                6,
+                &clock,
+                2 * 60
+            ),
+            Err(GetPriceError::MismatchedPriceAccount.into())
+        );
+        let lower = get_oracle_price_bound_from_feed_id_fp32(
+            &feed,
+            &account_info,
+            &PricePolicy::new(2 * 60),
+            PriceBound::Lower,
+            9,
+            6,
+            &clock,
+        )
+        .unwrap();
+        let upper = get_oracle_price_bound_from_feed_id_fp64(
+            &feed,
+            &account_info,
+            &PricePolicy::new(2 * 60),
+            PriceBound::Upper,
+            9,
+            6,
+            &clock,
+        )
+        .unwrap();
+        assert!(lower < 565179032 && (upper >> 32) > 565179032);
+        let policy = PricePolicy {
+            max_conf_ratio_fp32: 0,
+            ..PricePolicy::new(2 * 60)
+        };
+        assert_eq!(
+            get_oracle_price_bound_from_feed_id_fp32(
+                &feed,
+                &account_info,
+                &policy,
+                PriceBound::Lower,
+                9,
+                6,
+                &clock
+            ),
+            Err(GetPriceError::ConfidenceTooWide.into())
+        );
+        let policy = PricePolicy::new(2 * 60);
+        let accounts = [account_info.clone(), account_info.clone()];
+        let spot = with_pull_oracle(&feed, &account_info, |oracle| oracle.price()).unwrap();
+        assert_eq!(
+            get_median_price_from_feed_id(&feed, &accounts, &policy, 0, &clock),
+            Ok(spot)
+        );
+        assert_eq!(
+            get_price_with_fallback_from_feed_id(&feed, &accounts, &policy, &clock),
+            Ok(spot)
+        );
+        assert_eq!(
+            get_price_with_fallback_from_feed_id(&feed, &accounts, &policy, &stale_clock),
+            Err(GetPriceError::PriceTooOld.into())
+        );
+        // SOL in SOL
+        assert_eq!(
+            get_cross_rate_fp32_v2(
+                SupportedToken::Sol,
+                &account_info,
+                SupportedToken::Sol,
+                &account_info,
+                &clock,
+                2 * 60
+            ),
+            Ok(1 << 32)
+        );
+        assert_eq!(
+            parse_price_v2(&account_data[..4]).err(),
+            Some(GetPriceError::InvalidPriceAccount.into())
+        );
+        assert_eq!(
+            parse_price_v2(&account_data[..40]).err(),
+            Some(GetPriceError::InvalidPriceAccount.into())
+        );
+    }
+
+    #[cfg(feature = "mock-oracle")]
+    #[test]
+    fn test_mock_oracle() {
+        use crate::oracle::{MockOracle, MOCK_ORACLE_OWNER};
+
+        let key = Pubkey::new_unique();
+        let mut account_data = MockOracle::new(14_845_000_000, -8, 100).to_account_data();
+        let mut lamports = u64::MAX;
+        let mut account_info = AccountInfo {
+            data: Rc::new(RefCell::new(&mut account_data[..])),
+            key: &key,
+            lamports: Rc::new(RefCell::new(&mut lamports)),
+            owner: &MOCK_ORACLE_OWNER,
+            #[allow(deprecated)]
+            _unused: u64::MAX,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+        let clock = Clock {
+            unix_timestamp: 160,
+            ..Default::default()
+        };
+        let price_fp32 =
+            get_oracle_price_fp32_v2(&SupportedToken::Sol.mint(), &account_info, 9, 6, &clock, 60)
+                .unwrap();
+        assert_eq!(price_fp32, (148_450u128 << 32) as u64 / 1_000_000);
+        assert!(
+            get_oracle_price_from_feed_id_fp32(&[0; 32], &account_info, 9, 6, &clock, 59).is_err()
+        );
+
+        // Mock accounts with another owner are treated as Pyth accounts
+        account_info.owner = &PYTH_RECEIVER;
+        assert!(
+            get_oracle_price_from_feed_id_fp32(&[0; 32], &account_info, 9, 6, &clock, 60).is_err()
+        );
+    }
+
+    #[test]
+    fn test_twap() {
+        // SOL/USD TWAP of 148.45 ± 0.12 over the 600 seconds ending at 1_700_000_600
+        let mut account_data = [
+            104, 192, 188, 72, 246, 166, 12, 81, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+            7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 239, 13, 139, 111, 218, 44, 235, 164, 29,
+            161, 93, 64, 149, 209, 218, 57, 42, 13, 47, 142, 208, 198, 199, 188, 15, 76, 250, 200,
+            194, 128, 181, 109, 0, 241, 83, 101, 0, 0, 0, 0, 88, 243, 83, 101, 0, 0, 0, 0, 64, 185,
+            212, 116, 3, 0, 0, 0, 0, 27, 183, 0, 0, 0, 0, 0, 248, 255, 255, 255, 232, 3, 0, 0,
+        ];
+        let update = parse_twap_update(&account_data).unwrap();
+        assert_eq!(update.write_authority, Pubkey::new_from_array([7; 32]));
+        assert_eq!(update.twap.price, 14_845_000_000);
+        assert_eq!(update.twap.down_slots_ratio, 1_000);
+
+        let key = Pubkey::new_unique();
+        let mut lamports = u64::MAX;
+        let account_info = AccountInfo {
+            data: Rc::new(RefCell::new(&mut account_data[..])),
+            key: &key,
+            lamports: Rc::new(RefCell::new(&mut lamports)),
+            owner: &PYTH_RECEIVER,
+            #[allow(deprecated)]
+            _unused: u64::MAX,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+        let clock = Clock {
+            unix_timestamp: 1_700_000_660,
+            ..Default::default()
+        };
+        let feed = SupportedToken::Sol.price_feed();
+        let price_fp32 = get_oracle_twap_fp32_v2(
+            &SupportedToken::Sol.mint(),
+            &account_info,
+            600,
+            9,
+            6,
+            &clock,
+            60,
+        )
+        .unwrap();
+        assert_eq!(price_fp32, (148_450u128 << 32) as u64 / 1_000_000);
+        let price_fp64 =
+            get_oracle_twap_from_feed_id_fp64(&feed, &account_info, 600, 9, 6, &clock, 60).unwrap();
+        assert_eq!(price_fp64, (148_450u128 << 64) / 1_000_000);
+
+        assert_eq!(
+            get_oracle_twap_from_feed_id_fp32(&feed, &account_info, 600, 9, 6, &clock, 59),
+            Err(GetPriceError::PriceTooOld.into())
+        );
+        assert_eq!(
+            get_oracle_twap_from_feed_id_fp32(&feed, &account_info, 300, 9, 6, &clock, 60),
+            Err(GetPriceError::InvalidWindowSize.into())
+        );
+        assert_eq!(
+            get_oracle_twap_from_feed_id_fp32(&[0; 32], &account_info, 600, 9, 6, &clock, 60),
+            Err(GetPriceError::MismatchedFeedId.into())
+        );
+        assert!(parse_price_v2(&account_info.data.borrow()).is_err());
     }
 }
@@ -0,0 +1,335 @@
+//! Resolution of token mints into Pyth pull oracle feeds.
+//!
+//! [`SupportedTokenResolver`] covers the tokens of [`SupportedToken`], while [`FeedTable`] lets
+//! programs keep their own mint to feed registry in an account updated by an admin.
+use bytemuck::{Pod, Zeroable};
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, msg, program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use std::mem::size_of;
+
+use super::{get_pyth_feed_account_key, utils::FeedId, utils::GetPriceError};
+use crate::{
+    checks::{check_account_key, check_signer},
+    tokens::SupportedToken,
+};
+
+/// Returns the Pyth pull oracle feed of a token mint
+pub trait FeedResolver {
+    /// Returns the feed id of `mint` and the shard of its receiver price feed account
+    fn resolve(&self, mint: &Pubkey) -> Result<(FeedId, u16), ProgramError>;
+
+    /// Returns the key of the price feed account of `mint` pushed by the default Pyth sponsor
+    fn price_feed_account_key(&self, mint: &Pubkey) -> Result<Pubkey, ProgramError> {
+        let (feed_id, shard) = self.resolve(mint)?;
+        Ok(get_pyth_feed_account_key(shard, &feed_id))
+    }
+}
+
+/// Resolves the mints of [`SupportedToken`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SupportedTokenResolver;
+
+impl FeedResolver for SupportedTokenResolver {
+    fn resolve(&self, mint: &Pubkey) -> Result<(FeedId, u16), ProgramError> {
+        let token = SupportedToken::from_mint(mint).map_err(|_| {
+            msg!("Unsupported token mint: {}", mint);
+            GetPriceError::UnsupportedToken
+        })?;
+        Ok((token.price_feed(), token.price_feed_shard()))
+    }
+}
+
+/// The tag at the start of a feed table account
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedTableTag {
+    Uninitialized,
+    FeedTable,
+}
+
+#[derive(Clone, Copy, Zeroable, Pod, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FeedTableHeader {
+    /// A [`FeedTableTag`]
+    pub tag: u64,
+    /// The only key allowed to update the table
+    pub admin: Pubkey,
+    /// Number of entries in use
+    pub len: u64,
+}
+
+#[derive(Clone, Copy, Zeroable, Pod, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct FeedTableEntry {
+    pub mint: Pubkey,
+    pub feed_id: FeedId,
+    pub shard: u16,
+    pub _padding: [u8; 6],
+}
+
+/// A mint to feed registry stored in a `Pod` account: a [`FeedTableHeader`] followed by as many
+/// [`FeedTableEntry`] as the account can hold.
+///
+/// The account data must be 8-byte aligned, which is always the case on chain, and the table
+/// created with [`FeedTable::initialize`].
+pub struct FeedTable<'a> {
+    pub header: &'a mut FeedTableHeader,
+    entries: &'a mut [FeedTableEntry],
+}
+
+impl<'a> FeedTable<'a> {
+    /// Returns the size of an account holding up to `capacity` entries
+    pub const fn space(capacity: usize) -> usize {
+        size_of::<FeedTableHeader>() + capacity * size_of::<FeedTableEntry>()
+    }
+
+    /// Loads an initialized table
+    pub fn from_buffer(data: &'a mut [u8]) -> Result<Self, ProgramError> {
+        let table = Self::from_buffer_unchecked(data)?;
+        if table.header.tag != FeedTableTag::FeedTable as u64 {
+            msg!("Invalid feed table tag");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(table)
+    }
+
+    /// Initializes an empty table in an uninitialized account
+    pub fn initialize(data: &'a mut [u8], admin: &Pubkey) -> Result<Self, ProgramError> {
+        let table = Self::from_buffer_unchecked(data)?;
+        if table.header.tag != FeedTableTag::Uninitialized as u64 {
+            msg!("The feed table is already initialized");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+        check_admin_key(admin)?;
+        *table.header = FeedTableHeader {
+            tag: FeedTableTag::FeedTable as u64,
+            admin: *admin,
+            len: 0,
+        };
+        Ok(table)
+    }
+
+    fn from_buffer_unchecked(data: &'a mut [u8]) -> Result<Self, ProgramError> {
+        if data.len() < size_of::<FeedTableHeader>() {
+            msg!("The feed table account is too small");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let (header, entries) = data.split_at_mut(size_of::<FeedTableHeader>());
+        let capacity = entries.len() / size_of::<FeedTableEntry>();
+        let header: &mut FeedTableHeader = bytemuck::try_from_bytes_mut(header).map_err(|e| {
+            msg!("Invalid feed table account: {}", e);
+            ProgramError::InvalidAccountData
+        })?;
+        let entries: &mut [FeedTableEntry] =
+            bytemuck::try_cast_slice_mut(&mut entries[..capacity * size_of::<FeedTableEntry>()])
+                .map_err(|e| {
+                    msg!("Invalid feed table account: {}", e);
+                    ProgramError::InvalidAccountData
+                })?;
+        if header.len as usize > capacity {
+            msg!("The feed table length exceeds its capacity");
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(Self { header, entries })
+    }
+
+    /// Returns the maximum number of entries of the table
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns the entries in use
+    pub fn entries(&self) -> &[FeedTableEntry] {
+        &self.entries[..self.header.len as usize]
+    }
+
+    pub fn get(&self, mint: &Pubkey) -> Option<&FeedTableEntry> {
+        self.entries().iter().find(|e| &e.mint == mint)
+    }
+
+    fn check_admin(&self, admin: &AccountInfo) -> ProgramResult {
+        check_account_key(admin, &self.header.admin)?;
+        check_signer(admin)
+    }
+
+    /// Adds the feed of `mint` or replaces it if it is already registered
+    pub fn set(
+        &mut self,
+        admin: &AccountInfo,
+        mint: &Pubkey,
+        feed_id: &FeedId,
+        shard: u16,
+    ) -> ProgramResult {
+        self.check_admin(admin)?;
+        let len = self.header.len as usize;
+        let index = match self.entries().iter().position(|e| &e.mint == mint) {
+            Some(i) => i,
+            None if len < self.capacity() => {
+                self.header.len += 1;
+                len
+            }
+            None => {
+                msg!("The feed table is full");
+                return Err(ProgramError::AccountDataTooSmall);
+            }
+        };
+        self.entries[index] = FeedTableEntry {
+            mint: *mint,
+            feed_id: *feed_id,
+            shard,
+            _padding: [0; 6],
+        };
+        Ok(())
+    }
+
+    /// Removes the feed of `mint`, the last entry taking its place
+    pub fn remove(&mut self, admin: &AccountInfo, mint: &Pubkey) -> ProgramResult {
+        self.check_admin(admin)?;
+        let index = self
+            .entries()
+            .iter()
+            .position(|e| &e.mint == mint)
+            .ok_or_else(|| {
+                msg!("Unsupported token mint: {}", mint);
+                ProgramError::from(GetPriceError::UnsupportedToken)
+            })?;
+        let last = self.header.len as usize - 1;
+        self.entries.swap(index, last);
+        self.entries[last] = FeedTableEntry::zeroed();
+        self.header.len -= 1;
+        Ok(())
+    }
+
+    /// Hands the table over to `new_admin`
+    pub fn set_admin(&mut self, admin: &AccountInfo, new_admin: &Pubkey) -> ProgramResult {
+        self.check_admin(admin)?;
+        check_admin_key(new_admin)?;
+        self.header.admin = *new_admin;
+        Ok(())
+    }
+}
+
+/// The default key would let anyone sign for the table
+fn check_admin_key(admin: &Pubkey) -> ProgramResult {
+    if admin == &Pubkey::default() {
+        msg!("The feed table admin cannot be the default key");
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(())
+}
+
+impl FeedResolver for FeedTable<'_> {
+    fn resolve(&self, mint: &Pubkey) -> Result<(FeedId, u16), ProgramError> {
+        let entry = self.get(mint).ok_or_else(|| {
+            msg!("Unsupported token mint: {}", mint);
+            GetPriceError::UnsupportedToken
+        })?;
+        Ok((entry.feed_id, entry.shard))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn test_supported_tokens() {
+        let resolver = SupportedTokenResolver;
+        let mint = SupportedToken::Sol.mint();
+        assert_eq!(
+            resolver.resolve(&mint),
+            Ok((SupportedToken::Sol.price_feed(), 0))
+        );
+        assert_eq!(
+            resolver.price_feed_account_key(&mint),
+            Ok(SupportedToken::Sol.price_feed_account_key())
+        );
+        assert_eq!(
+            resolver.resolve(&Pubkey::new_unique()),
+            Err(GetPriceError::UnsupportedToken.into())
+        );
+    }
+
+    #[test]
+    fn test_feed_table() {
+        let mut buffer = vec![0u64; FeedTable::space(2) / 8];
+        assert_eq!(
+            FeedTable::from_buffer(bytemuck::cast_slice_mut(&mut buffer)).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+        assert_eq!(
+            FeedTable::initialize(bytemuck::cast_slice_mut(&mut buffer), &Pubkey::default()).err(),
+            Some(ProgramError::InvalidArgument)
+        );
+        let admin_key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = [];
+        let mut admin = AccountInfo {
+            key: &admin_key,
+            is_signer: true,
+            is_writable: false,
+            lamports: Rc::new(RefCell::new(&mut lamports)),
+            data: Rc::new(RefCell::new(&mut data)),
+            owner: &admin_key,
+            executable: false,
+            #[allow(deprecated)]
+            _unused: 0,
+        };
+        FeedTable::initialize(bytemuck::cast_slice_mut(&mut buffer), &admin_key).unwrap();
+        assert_eq!(
+            FeedTable::initialize(bytemuck::cast_slice_mut(&mut buffer), &admin_key).err(),
+            Some(ProgramError::AccountAlreadyInitialized)
+        );
+        let mut table = FeedTable::from_buffer(bytemuck::cast_slice_mut(&mut buffer)).unwrap();
+        assert_eq!(table.capacity(), 2);
+
+        let (a, b, c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        table.set(&admin, &a, &[1; 32], 0).unwrap();
+        table.set(&admin, &b, &[2; 32], 1).unwrap();
+        table.set(&admin, &a, &[3; 32], 2).unwrap();
+        assert_eq!(table.resolve(&a), Ok(([3; 32], 2)));
+        assert_eq!(table.resolve(&b), Ok(([2; 32], 1)));
+        assert_eq!(
+            table.set(&admin, &c, &[4; 32], 0),
+            Err(ProgramError::AccountDataTooSmall)
+        );
+
+        table.remove(&admin, &a).unwrap();
+        assert_eq!(table.entries().len(), 1);
+        assert_eq!(
+            table.resolve(&a),
+            Err(GetPriceError::UnsupportedToken.into())
+        );
+        assert_eq!(table.resolve(&b), Ok(([2; 32], 1)));
+
+        admin.is_signer = false;
+        assert_eq!(
+            table.set(&admin, &c, &[4; 32], 0),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+        admin.is_signer = true;
+        assert_eq!(
+            table.set_admin(&admin, &Pubkey::default()),
+            Err(ProgramError::InvalidArgument)
+        );
+        table.set_admin(&admin, &Pubkey::new_unique()).unwrap();
+        assert_eq!(table.remove(&admin, &b), Err(ProgramError::InvalidArgument));
+
+        // Reloading the account keeps the entries
+        let table = FeedTable::from_buffer(bytemuck::cast_slice_mut(&mut buffer)).unwrap();
+        assert_eq!(table.resolve(&b), Ok(([2; 32], 1)));
+
+        // Accounts of another type are rejected
+        buffer[0] = FeedTableTag::FeedTable as u64 + 1;
+        assert_eq!(
+            FeedTable::from_buffer(bytemuck::cast_slice_mut(&mut buffer)).err(),
+            Some(ProgramError::InvalidAccountData)
+        );
+    }
+}
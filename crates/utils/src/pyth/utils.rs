@@ -1,7 +1,7 @@
-use std::convert::TryInto;
+use std::{convert::TryInto, fmt};
 
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::{clock::Clock, pubkey::Pubkey};
+use solana_program::{clock::Clock, program_error::ProgramError, pubkey::Pubkey};
 
 macro_rules! check {
     ($cond:expr, $err:expr) => {
@@ -13,6 +13,55 @@ macro_rules! check {
 
 pub type FeedId = [u8; 32];
 
+/// Parses a hex encoded feed id, with or without a `0x` prefix
+pub const fn feed_id_from_hex(s: &str) -> Result<FeedId, GetPriceError> {
+    let bytes = s.as_bytes();
+    let start = if bytes.len() >= 2 && bytes[0] == b'0' && (bytes[1] == b'x' || bytes[1] == b'X') {
+        2
+    } else {
+        0
+    };
+    if bytes.len() - start != 64 {
+        return Err(GetPriceError::FeedIdMustBe32Bytes);
+    }
+    let mut feed_id = [0; 32];
+    let mut i = 0;
+    while i < 32 {
+        match (
+            hex_digit(bytes[start + 2 * i]),
+            hex_digit(bytes[start + 2 * i + 1]),
+        ) {
+            (Some(high), Some(low)) => feed_id[i] = (high << 4) | low,
+            _ => return Err(GetPriceError::FeedIdNonHexCharacter),
+        }
+        i += 1;
+    }
+    Ok(feed_id)
+}
+
+const fn hex_digit(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Displays a feed id as `0x` followed by 64 lowercase hex characters
+#[derive(Debug, Clone, Copy)]
+pub struct FeedIdDisplay<'a>(pub &'a FeedId);
+
+impl fmt::Display for FeedIdDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("0x")?;
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Price {
     pub price: i64,
     pub conf: u64,
@@ -20,7 +69,7 @@ pub struct Price {
     pub publish_time: i64,
 }
 
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VerificationLevel {
     Partial {
         #[allow(unused)]
@@ -45,7 +94,7 @@ impl VerificationLevel {
     }
 }
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GetPriceError {
     #[error("This price feed update's age exceeds the requested maximum age")]
     PriceTooOld = 10000, // Big number to avoid conflicts with the SDK user's program error codes
@@ -59,6 +108,36 @@ pub enum GetPriceError {
     FeedIdMustBe32Bytes,
     #[error("Feed id contains non-hex characters")]
     FeedIdNonHexCharacter,
+    #[error("The account is not a valid price account")]
+    InvalidPriceAccount,
+    #[error("The price account is not owned by the expected program")]
+    InvalidPriceAccountOwner,
+    #[error("The price account was not found in the product and mapping accounts")]
+    PriceAccountNotFound,
+    #[error("There is no price feed for this token")]
+    UnsupportedToken,
+    #[error("The price is negative")]
+    NegativePrice,
+    #[error("The price overflows once scaled")]
+    PriceOverflow,
+    #[error("This price was published too many slots ago")]
+    PriceTooOldInSlots,
+    #[error("The confidence interval of this price is too wide")]
+    ConfidenceTooWide,
+    #[error("Cannot divide by a zero price")]
+    ZeroPrice,
+    #[error("No price was provided")]
+    NotEnoughPrices,
+    #[error("A price deviates too much from the median")]
+    PriceDeviationTooHigh,
+    #[error("The price account is not the feed account of the resolved shard")]
+    MismatchedPriceAccount,
+}
+
+impl From<GetPriceError> for ProgramError {
+    fn from(e: GetPriceError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
 }
 
 #[derive(BorshSerialize, BorshDeserialize)]
@@ -96,6 +175,9 @@ pub struct PriceUpdateV2 {
 }
 
 impl PriceUpdateV2 {
+    /// Size of the receiver accounts, which fits the largest verification level
+    pub const LEN: usize = 8 + 32 + 2 + 32 + 8 + 8 + 4 + 8 + 8 + 8 + 8 + 8;
+
     /// Get a `Price` from a `PriceUpdateV2` account for a given `FeedId`.
     ///
     /// # Warning
@@ -161,7 +243,7 @@ impl PriceUpdateV2 {
         check!(
             price
                 .publish_time
-                .saturating_add(maximum_age.try_into().unwrap())
+                .saturating_add(maximum_age.try_into().unwrap_or(i64::MAX))
                 >= clock.unix_timestamp,
             GetPriceError::PriceTooOld
         );
@@ -204,3 +286,107 @@ impl PriceUpdateV2 {
         )
     }
 }
+
+/// A time-weighted average price over `[start_time, end_time]`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TwapPrice {
+    /// `FeedId` but avoid the type alias because of compatibility issues with Anchor's `idl-build` feature.
+    pub feed_id: [u8; 32],
+    pub start_time: i64,
+    pub end_time: i64,
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    /// Ratio out of 1_000_000, where a value of 1_000_000 represents all slots were missed and 0
+    /// represents no slots were missed.
+    pub down_slots_ratio: u32,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct TwapUpdate {
+    pub write_authority: Pubkey,
+    pub twap: TwapPrice,
+}
+
+impl TwapUpdate {
+    /// Get a `TwapPrice` from a `TwapUpdate` account for a given `FeedId`.
+    ///
+    /// # Warning
+    /// This function does not check how recent the TWAP is, nor its window size.
+    pub fn get_twap_unchecked(
+        &self,
+        feed_id: &FeedId,
+    ) -> std::result::Result<TwapPrice, GetPriceError> {
+        check!(
+            self.twap.feed_id == *feed_id,
+            GetPriceError::MismatchedFeedId
+        );
+        Ok(self.twap)
+    }
+
+    /// Get a `TwapPrice` from a `TwapUpdate` account for a given `FeedId`, ending no more than
+    /// `maximum_age` seconds ago and computed over exactly `window_seconds`.
+    pub fn get_twap_no_older_than(
+        &self,
+        clock: &Clock,
+        maximum_age: u64,
+        window_seconds: u64,
+        feed_id: &FeedId,
+    ) -> std::result::Result<TwapPrice, GetPriceError> {
+        let twap = self.get_twap_unchecked(feed_id)?;
+        check!(
+            twap.end_time
+                .saturating_add(maximum_age.try_into().unwrap_or(i64::MAX))
+                >= clock.unix_timestamp,
+            GetPriceError::PriceTooOld
+        );
+        let window = twap.end_time.saturating_sub(twap.start_time);
+        check!(
+            window > 0 && window as u64 == window_seconds,
+            GetPriceError::InvalidWindowSize
+        );
+        Ok(twap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bonfida_macros::feed_id;
+
+    const SOL_USD: &str = "ef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+
+    #[test]
+    fn test_feed_id_hex() {
+        const FEED_ID: FeedId = match feed_id_from_hex(SOL_USD) {
+            Ok(feed_id) => feed_id,
+            Err(_) => panic!(),
+        };
+        assert_eq!(FEED_ID[..2], [0xef, 0x0d]);
+        assert_eq!(
+            feed_id_from_hex(&format!("0x{}", SOL_USD.to_uppercase())),
+            Ok(FEED_ID)
+        );
+        assert_eq!(
+            feed_id!("0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d"),
+            FEED_ID
+        );
+        assert_eq!(
+            FeedIdDisplay(&FEED_ID).to_string(),
+            format!("0x{}", SOL_USD)
+        );
+
+        assert_eq!(
+            feed_id_from_hex(&SOL_USD[2..]),
+            Err(GetPriceError::FeedIdMustBe32Bytes)
+        );
+        assert_eq!(
+            feed_id_from_hex("0x"),
+            Err(GetPriceError::FeedIdMustBe32Bytes)
+        );
+        assert_eq!(
+            feed_id_from_hex(&SOL_USD.replace('e', "g")),
+            Err(GetPriceError::FeedIdNonHexCharacter)
+        );
+    }
+}
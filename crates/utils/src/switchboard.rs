@@ -0,0 +1,233 @@
+//! Switchboard on-demand pull feeds, parsed without the Switchboard SDK.
+//!
+//! Only the fields needed to read the latest result are decoded, at their offsets in the
+//! zero-copy `PullFeedAccountData` layout.
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, msg, program_error::ProgramError, pubkey,
+    pubkey::Pubkey,
+};
+use std::convert::{TryFrom, TryInto};
+
+#[cfg(feature = "mock-oracle")]
+use crate::oracle::MockOracle;
+use crate::{
+    decimals::pow10,
+    oracle::{OraclePrice, PriceOracle, PricePolicy},
+    pyth::utils::GetPriceError,
+};
+
+pub const SWITCHBOARD_ON_DEMAND_PROGRAM_ID: Pubkey =
+    pubkey!("SBondMDrcV3K4kxZR1HNVT7osZxAHVHgYXL5Ze1oMUv");
+pub const PULL_FEED_DISCRIMINATOR: [u8; 8] = [196, 27, 108, 196, 10, 215, 219, 40];
+/// Size of a pull feed account, discriminator included
+pub const PULL_FEED_LEN: usize = 3_208;
+/// Switchboard results are fixed point numbers with 18 decimals
+pub const SWITCHBOARD_DECIMALS: u32 = 18;
+
+// Offsets in the account data, discriminator included
+const QUEUE_OFFSET: usize = 8 + 2_080;
+const FEED_HASH_OFFSET: usize = 8 + 2_112;
+const LAST_UPDATE_TIMESTAMP_OFFSET: usize = 8 + 2_208;
+const RESULT_OFFSET: usize = 8 + 2_256;
+const RESULT_STD_DEV_OFFSET: usize = RESULT_OFFSET + 16;
+const RESULT_NUM_SAMPLES_OFFSET: usize = RESULT_OFFSET + 96;
+const RESULT_SLOT_OFFSET: usize = RESULT_OFFSET + 104;
+
+/// The latest result of a Switchboard on-demand pull feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PullFeed {
+    pub queue: Pubkey,
+    pub feed_hash: [u8; 32],
+    /// Unix timestamp of the last update
+    pub last_update_timestamp: i64,
+    /// Median of the oracle submissions, with 18 decimals
+    pub value: i128,
+    /// Standard deviation of the oracle submissions, with 18 decimals
+    pub std_dev: i128,
+    pub num_samples: u8,
+    /// Slot of the latest result
+    pub slot: u64,
+}
+
+fn read<const N: usize>(data: &[u8], offset: usize) -> [u8; N] {
+    data[offset..offset + N].try_into().unwrap()
+}
+
+impl PullFeed {
+    /// Parses the data of a pull feed account, checking its discriminator and size
+    pub fn parse(data: &[u8]) -> Result<Self, ProgramError> {
+        if !data.starts_with(&PULL_FEED_DISCRIMINATOR) || data.len() < PULL_FEED_LEN {
+            msg!("Invalid Switchboard pull feed account");
+            return Err(GetPriceError::InvalidPriceAccount.into());
+        }
+        Ok(Self {
+            queue: Pubkey::new_from_array(read(data, QUEUE_OFFSET)),
+            feed_hash: read(data, FEED_HASH_OFFSET),
+            last_update_timestamp: i64::from_le_bytes(read(data, LAST_UPDATE_TIMESTAMP_OFFSET)),
+            value: i128::from_le_bytes(read(data, RESULT_OFFSET)),
+            std_dev: i128::from_le_bytes(read(data, RESULT_STD_DEV_OFFSET)),
+            num_samples: data[RESULT_NUM_SAMPLES_OFFSET],
+            slot: u64::from_le_bytes(read(data, RESULT_SLOT_OFFSET)),
+        })
+    }
+
+    /// Parses a pull feed account, checking its owner
+    pub fn from_account_info(account: &AccountInfo) -> Result<Self, ProgramError> {
+        if account.owner != &SWITCHBOARD_ON_DEMAND_PROGRAM_ID {
+            msg!(
+                "Switchboard pull feeds must be owned by {}",
+                SWITCHBOARD_ON_DEMAND_PROGRAM_ID
+            );
+            return Err(GetPriceError::InvalidPriceAccountOwner.into());
+        }
+        Self::parse(&account.try_borrow_data()?)
+    }
+}
+
+impl PriceOracle for PullFeed {
+    /// Returns the result with as many of its 18 decimals as an `i64` allows, the price being
+    /// rounded down and the standard deviation, used as confidence, rounded up
+    fn price(&self) -> Result<OraclePrice, ProgramError> {
+        if self.num_samples == 0 {
+            msg!("The Switchboard feed has no result");
+            return Err(GetPriceError::NotEnoughPrices.into());
+        }
+        let value = u128::try_from(self.value).map_err(|_| GetPriceError::NegativePrice)?;
+        let std_dev = u128::try_from(self.std_dev).unwrap_or(0);
+        let mut dropped_digits = 0;
+        while value / pow10(dropped_digits).unwrap() > i64::MAX as u128 {
+            dropped_digits += 1;
+        }
+        let divisor = pow10(dropped_digits).unwrap();
+        Ok(OraclePrice {
+            price: (value / divisor) as i64,
+            conf: u64::try_from(std_dev.div_ceil(divisor))
+                .map_err(|_| GetPriceError::PriceOverflow)?,
+            exponent: dropped_digits as i32 - SWITCHBOARD_DECIMALS as i32,
+            publish_time: self.last_update_timestamp,
+            slot: self.slot,
+        })
+    }
+
+    fn ema_price(&self) -> Result<OraclePrice, ProgramError> {
+        msg!("Switchboard pull feeds have no EMA price");
+        Err(GetPriceError::InvalidPriceAccount.into())
+    }
+}
+
+/// Returns the fp32 price of one raw base token in raw quote tokens from a Switchboard pull feed
+/// whose result was produced at most `max_stale_slots` slots ago
+pub fn get_switchboard_price_fp32(
+    account: &AccountInfo,
+    max_stale_slots: u64,
+    base_decimals: u8,
+    quote_decimals: u8,
+    clock: &Clock,
+) -> Result<u64, ProgramError> {
+    let policy = PricePolicy {
+        max_age_slots: max_stale_slots,
+        ..PricePolicy::new(u64::MAX)
+    };
+    #[cfg(feature = "mock-oracle")]
+    {
+        if MockOracle::is_mock_account_info(account) {
+            let price =
+                MockOracle::from_account_info(account)?.price_with_policy(clock, &policy)?;
+            return Ok(price.to_fp32(base_decimals, quote_decimals)?);
+        }
+    }
+    let price = PullFeed::from_account_info(account)?.price_with_policy(clock, &policy)?;
+    let final_price = price
+        .to_fp32(base_decimals, quote_decimals)
+        .inspect_err(|e| msg!("Cannot convert the Switchboard price: {}", e))?;
+
+    msg!("Switchboard FP32 price value: {:?}", final_price);
+
+    Ok(final_price)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    fn fixture(value: i128, std_dev: i128, slot: u64) -> Vec<u8> {
+        let mut data = vec![0; PULL_FEED_LEN];
+        data[..8].copy_from_slice(&PULL_FEED_DISCRIMINATOR);
+        data[QUEUE_OFFSET..QUEUE_OFFSET + 32].copy_from_slice(&[3; 32]);
+        data[FEED_HASH_OFFSET..FEED_HASH_OFFSET + 32].copy_from_slice(&[4; 32]);
+        data[LAST_UPDATE_TIMESTAMP_OFFSET..LAST_UPDATE_TIMESTAMP_OFFSET + 8]
+            .copy_from_slice(&1_700_000_000i64.to_le_bytes());
+        data[RESULT_OFFSET..RESULT_OFFSET + 16].copy_from_slice(&value.to_le_bytes());
+        data[RESULT_STD_DEV_OFFSET..RESULT_STD_DEV_OFFSET + 16]
+            .copy_from_slice(&std_dev.to_le_bytes());
+        data[RESULT_NUM_SAMPLES_OFFSET] = 5;
+        data[RESULT_SLOT_OFFSET..RESULT_SLOT_OFFSET + 8].copy_from_slice(&slot.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn test_parse() {
+        // 148.45 ± 0.1
+        let data = fixture(148_450_000_000_000_000_000, 100_000_000_000_000_000, 1_000);
+        let feed = PullFeed::parse(&data).unwrap();
+        assert_eq!(feed.queue, Pubkey::new_from_array([3; 32]));
+        assert_eq!(feed.feed_hash, [4; 32]);
+        assert_eq!(feed.num_samples, 5);
+        assert_eq!(
+            feed.price().unwrap(),
+            OraclePrice {
+                price: 1_484_500_000_000_000_000,
+                conf: 1_000_000_000_000_000,
+                exponent: -16,
+                publish_time: 1_700_000_000,
+                slot: 1_000,
+            }
+        );
+
+        assert!(PullFeed::parse(&data[..PULL_FEED_LEN - 1]).is_err());
+        assert!(PullFeed::parse(&[0; PULL_FEED_LEN]).is_err());
+        let negative = PullFeed::parse(&fixture(-1, 0, 1_000)).unwrap();
+        assert_eq!(negative.price(), Err(GetPriceError::NegativePrice.into()));
+        let small = PullFeed::parse(&fixture(5, 3, 1_000))
+            .unwrap()
+            .price()
+            .unwrap();
+        assert_eq!((small.price, small.conf, small.exponent), (5, 3, -18));
+    }
+
+    #[test]
+    fn test_get_price() {
+        let mut data = fixture(148_450_000_000_000_000_000, 0, 1_000);
+        let key = Pubkey::new_unique();
+        let mut lamports = u64::MAX;
+        let mut account_info = AccountInfo {
+            data: Rc::new(RefCell::new(&mut data[..])),
+            key: &key,
+            lamports: Rc::new(RefCell::new(&mut lamports)),
+            owner: &SWITCHBOARD_ON_DEMAND_PROGRAM_ID,
+            #[allow(deprecated)]
+            _unused: u64::MAX,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+        let clock = Clock {
+            slot: 1_010,
+            ..Default::default()
+        };
+        assert_eq!(
+            get_switchboard_price_fp32(&account_info, 10, 9, 6, &clock),
+            Ok((148_450u128 << 32) as u64 / 1_000_000)
+        );
+        assert_eq!(
+            get_switchboard_price_fp32(&account_info, 9, 9, 6, &clock),
+            Err(GetPriceError::PriceTooOldInSlots.into())
+        );
+        account_info.owner = &key;
+        assert_eq!(
+            get_switchboard_price_fp32(&account_info, 10, 9, 6, &clock),
+            Err(GetPriceError::InvalidPriceAccountOwner.into())
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Timestamp helpers built on `Clock::unix_timestamp`.
+use bytemuck::{Pod, Zeroable};
+use solana_program::{clock::Clock, msg, program_error::ProgramError};
+
+pub const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Returns the number of seconds between `last_ts` and `now_ts`, failing if the clock went
+/// backwards
+pub fn elapsed_seconds(last_ts: i64, now_ts: i64) -> Result<u64, ProgramError> {
+    if now_ts < last_ts {
+        msg!("Clock regression: {} is before {}", now_ts, last_ts);
+        return Err(ProgramError::InvalidArgument);
+    }
+    Ok(now_ts.abs_diff(last_ts))
+}
+
+/// Returns the number of seconds between `last_ts` and `now_ts`, or zero if the clock went
+/// backwards
+pub fn saturating_elapsed_seconds(last_ts: i64, now_ts: i64) -> u64 {
+    if now_ts < last_ts {
+        0
+    } else {
+        now_ts.abs_diff(last_ts)
+    }
+}
+
+/// Returns the number of seconds elapsed since `last_ts` according to the clock, failing if the
+/// clock went backwards
+pub fn elapsed_seconds_since(last_ts: i64, clock: &Clock) -> Result<u64, ProgramError> {
+    elapsed_seconds(last_ts, clock.unix_timestamp)
+}
+
+/// Accumulates a value over time so that its time-weighted average between any two snapshots
+/// can be computed
+#[derive(Clone, Copy, Zeroable, Pod, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct TimeWeightedAverage {
+    /// Sum of value * seconds since the accumulator was created
+    pub cumulative: u128,
+    /// Value in effect since `last_update_ts`
+    pub last_value: u64,
+    pub last_update_ts: i64,
+}
+
+impl TimeWeightedAverage {
+    pub fn new(value: u64, ts: i64) -> Self {
+        Self {
+            cumulative: 0,
+            last_value: value,
+            last_update_ts: ts,
+        }
+    }
+
+    /// Returns the cumulative sum at `ts`, failing if `ts` is before the last update
+    pub fn cumulative_at(&self, ts: i64) -> Result<u128, ProgramError> {
+        let elapsed = elapsed_seconds(self.last_update_ts, ts)?;
+        (self.last_value as u128)
+            .checked_mul(elapsed as u128)
+            .and_then(|x| x.checked_add(self.cumulative))
+            .ok_or(ProgramError::ArithmeticOverflow)
+    }
+
+    /// Records a new value taking effect at `ts`
+    pub fn update(&mut self, value: u64, ts: i64) -> Result<(), ProgramError> {
+        self.cumulative = self.cumulative_at(ts)?;
+        self.last_value = value;
+        self.last_update_ts = ts;
+        Ok(())
+    }
+
+    /// Returns the time-weighted average between `earlier` and `ts`, rounded down.
+    ///
+    /// If no time has elapsed the current value is returned.
+    pub fn average_since(&self, earlier: &Self, ts: i64) -> Result<u64, ProgramError> {
+        let elapsed = elapsed_seconds(earlier.last_update_ts, ts)?;
+        if elapsed == 0 {
+            return Ok(self.last_value);
+        }
+        let delta = self
+            .cumulative_at(ts)?
+            .checked_sub(earlier.cumulative)
+            .ok_or(ProgramError::InvalidArgument)?;
+        Ok((delta / elapsed as u128) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elapsed() {
+        assert_eq!(elapsed_seconds(10, 25).unwrap(), 15);
+        assert_eq!(elapsed_seconds(i64::MIN, i64::MAX).unwrap(), u64::MAX);
+        assert!(elapsed_seconds(25, 10).is_err());
+        assert_eq!(saturating_elapsed_seconds(25, 10), 0);
+    }
+
+    #[test]
+    fn test_time_weighted_average() {
+        let mut twa = TimeWeightedAverage::new(100, 0);
+        let start = twa;
+        twa.update(200, 10).unwrap();
+        twa.update(400, 20).unwrap();
+        assert_eq!(twa.average_since(&start, 20).unwrap(), 150);
+        assert_eq!(twa.average_since(&start, 40).unwrap(), 275);
+        let snapshot = twa;
+        assert_eq!(twa.average_since(&snapshot, 20).unwrap(), 400);
+        assert!(twa.update(0, 19).is_err());
+        assert_eq!(std::mem::size_of::<TimeWeightedAverage>(), 32);
+    }
+}
@@ -0,0 +1,131 @@
+//! Linear vesting with an optional cliff.
+use bytemuck::{Pod, Zeroable};
+use solana_program::{clock::Clock, msg, program_error::ProgramError};
+
+use crate::fp_math::mul_div_floor;
+
+/// A vesting schedule which can be stored in a `Pod` account.
+///
+/// Tokens vest linearly from `start_ts` to `end_ts`. Nothing can be claimed before `cliff_ts`,
+/// at which point everything vested since `start_ts` becomes claimable at once.
+#[derive(Clone, Copy, Zeroable, Pod, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct VestingSchedule {
+    pub start_ts: i64,
+    pub cliff_ts: i64,
+    pub end_ts: i64,
+    pub total_amount: u64,
+    pub claimed_amount: u64,
+}
+
+impl VestingSchedule {
+    pub fn new(
+        start_ts: i64,
+        cliff_ts: i64,
+        end_ts: i64,
+        total_amount: u64,
+    ) -> Result<Self, ProgramError> {
+        if !(start_ts <= cliff_ts && cliff_ts <= end_ts) {
+            msg!("Vesting schedule timestamps must satisfy start <= cliff <= end");
+            return Err(ProgramError::InvalidArgument);
+        }
+        Ok(Self {
+            start_ts,
+            cliff_ts,
+            end_ts,
+            total_amount,
+            claimed_amount: 0,
+        })
+    }
+
+    /// Returns the total amount vested at `ts`, rounded down
+    pub fn vested_at(&self, ts: i64) -> u64 {
+        if ts < self.cliff_ts || ts < self.start_ts {
+            return 0;
+        }
+        if ts >= self.end_ts {
+            return self.total_amount;
+        }
+        let elapsed = ts.abs_diff(self.start_ts);
+        let duration = self.end_ts.abs_diff(self.start_ts);
+        // elapsed < duration so the result is below total_amount
+        mul_div_floor(self.total_amount as u128, elapsed as u128, duration as u128).unwrap() as u64
+    }
+
+    /// Returns the amount which can be claimed at `ts`
+    pub fn claimable_at(&self, ts: i64) -> u64 {
+        self.vested_at(ts).saturating_sub(self.claimed_amount)
+    }
+
+    /// Records a claim of `amount` at the current clock time
+    pub fn claim(&mut self, amount: u64, clock: &Clock) -> Result<(), ProgramError> {
+        if amount > self.claimable_at(clock.unix_timestamp) {
+            msg!("The claimed amount exceeds the vested amount");
+            return Err(ProgramError::InsufficientFunds);
+        }
+        self.claimed_amount += amount;
+        Ok(())
+    }
+
+    /// Records a claim of everything claimable at the current clock time and returns the amount
+    pub fn claim_all(&mut self, clock: &Clock) -> u64 {
+        let amount = self.claimable_at(clock.unix_timestamp);
+        self.claimed_amount += amount;
+        amount
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn clock(ts: i64) -> Clock {
+        Clock {
+            unix_timestamp: ts,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test() {
+        let mut schedule = VestingSchedule::new(100, 150, 200, 1_000).unwrap();
+        assert_eq!(schedule.claimable_at(0), 0);
+        assert_eq!(schedule.claimable_at(149), 0);
+        assert_eq!(schedule.claimable_at(150), 500);
+        assert_eq!(schedule.claimable_at(175), 750);
+        assert_eq!(schedule.claimable_at(250), 1_000);
+
+        schedule.claim(400, &clock(150)).unwrap();
+        assert_eq!(schedule.claimable_at(150), 100);
+        assert!(schedule.claim(101, &clock(150)).is_err());
+        assert_eq!(schedule.claim_all(&clock(300)), 600);
+        assert_eq!(schedule.claimable_at(300), 0);
+
+        // A clock regression never makes more tokens claimable
+        assert_eq!(schedule.claimable_at(120), 0);
+
+        assert!(VestingSchedule::new(100, 99, 200, 1).is_err());
+        let instant = VestingSchedule::new(100, 100, 100, 1_000).unwrap();
+        assert_eq!(instant.claimable_at(99), 0);
+        assert_eq!(instant.claimable_at(100), 1_000);
+        assert_eq!(std::mem::size_of::<VestingSchedule>(), 40);
+    }
+
+    proptest! {
+        #[test]
+        fn vesting_is_monotonic_and_bounded(
+            start in -(1i64 << 40)..(1i64 << 40),
+            cliff_delay in 0i64..(1 << 30),
+            duration in 0i64..(1 << 32),
+            total: u64,
+            ts in -(1i64 << 41)..(1i64 << 41),
+            step in 0i64..(1 << 30),
+        ) {
+            let cliff_delay = cliff_delay.min(duration);
+            let schedule = VestingSchedule::new(start, start + cliff_delay, start + duration, total).unwrap();
+            prop_assert!(schedule.vested_at(ts) <= schedule.vested_at(ts + step));
+            prop_assert!(schedule.vested_at(ts) <= total);
+        }
+    }
+}